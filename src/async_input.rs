@@ -0,0 +1,178 @@
+use std::{
+    future::{self, Future},
+    mem,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+use futures_core::Stream;
+
+use crate::{GamepadState, InputEvent, SteamdeckInput, SteamdeckShared};
+
+impl SteamdeckShared {
+    fn poll_ready<T>(&self, cx: &mut Context<'_>, mut ready: impl FnMut() -> Option<T>) -> Poll<T> {
+        if let Some(value) = ready() {
+            return Poll::Ready(value);
+        }
+        register(&mut self.wakers.lock().unwrap(), cx.waker());
+
+        // The input thread may have published between the check and registering the waker
+        match ready() {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+
+    pub(crate) fn wake_consumers(&self) {
+        for waker in self.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Futures recreated on every loop iteration, e.g. in `select!`, poll again from the same
+/// task; that task is only woken once.
+fn register(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+}
+
+impl SteamdeckInput {
+    /// Resolves once a Steam Deck is connected.
+    pub fn connected(&self) -> Connected<'_> {
+        Connected { input: self }
+    }
+
+    /// Takes the oldest queued event, waiting for one if there is none. Shares the queue
+    /// with `poll_events`.
+    pub async fn next_event(&self) -> InputEvent {
+        let shared = &self.shared;
+        future::poll_fn(|cx| shared.poll_ready(cx, || shared.events.lock().unwrap().pop_front()))
+            .await
+    }
+
+    pub fn events(&self) -> EventStream<'_> {
+        EventStream { input: self }
+    }
+
+    /// Yields the state as `fetch` would, once for every new packet, without affecting what
+    /// `fetch` returns. Slow consumers skip packets rather than queue them; see `drain_since`
+    /// for every packet.
+    pub fn states(&self) -> StateStream<'_> {
+        StateStream {
+            input: self,
+            last_received: None,
+            mouse_total: self.shared.gamepad.read().mouse_total,
+        }
+    }
+}
+
+pub struct Connected<'a> {
+    input: &'a SteamdeckInput,
+}
+
+impl Future for Connected<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let shared = &self.input.shared;
+        shared.poll_ready(cx, || shared.found.load(Ordering::SeqCst).then_some(()))
+    }
+}
+
+pub struct EventStream<'a> {
+    input: &'a SteamdeckInput,
+}
+
+impl Stream for EventStream<'_> {
+    type Item = InputEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<InputEvent>> {
+        let shared = &self.input.shared;
+        shared
+            .poll_ready(cx, || shared.events.lock().unwrap().pop_front())
+            .map(Some)
+    }
+}
+
+pub struct StateStream<'a> {
+    input: &'a SteamdeckInput,
+    last_received: Option<Instant>,
+    /// `GamepadState::mouse_total` of the previous item, for its own `mouse_delta`.
+    mouse_total: [f64; 2],
+}
+
+impl Stream for StateStream<'_> {
+    type Item = GamepadState;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<GamepadState>> {
+        let this = self.get_mut();
+        let input = this.input;
+        let last_received = &mut this.last_received;
+        let mouse_total = &mut this.mouse_total;
+        input
+            .shared
+            .poll_ready(cx, || {
+                let mut state = input
+                    .shared
+                    .snapshot(input.stale_timeout())
+                    .filter(|fetched| !fetched.stale)?
+                    .state;
+                if state.packet.received == *last_received {
+                    return None;
+                }
+                *last_received = state.packet.received;
+                let previous = mem::replace(mouse_total, state.mouse_total);
+                state.mouse_delta = [
+                    (state.mouse_total[0] - previous[0]) as f32,
+                    (state.mouse_total[1] - previous[1]) as f32,
+                ];
+                Some(state)
+            })
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::Wake,
+    };
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn repeated_polls_register_a_task_once() {
+        let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let second = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let first_waker = Waker::from(first.clone());
+
+        let mut wakers = Vec::new();
+        for _ in 0..1000 {
+            register(&mut wakers, &first_waker.clone());
+        }
+        register(&mut wakers, &Waker::from(second.clone()));
+        assert_eq!(wakers.len(), 2);
+
+        for waker in wakers.drain(..) {
+            waker.wake();
+        }
+        assert_eq!(first.0.load(Ordering::Relaxed), 1);
+        assert_eq!(second.0.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::{self, BufRead, Write};
+
+use steamdeck_input_rs::{
+    config::SteamdeckConfig,
+    diagnostics::{self, UDEV_RULE_PATH},
+};
+
+fn main() {
+    let install = std::env::args().any(|arg| arg == "--install");
+
+    let diagnosis = diagnostics::diagnose(&SteamdeckConfig::default());
+    print!("{diagnosis}");
+
+    if !diagnosis.needs_udev_rule() {
+        return;
+    }
+
+    println!(
+        "\nudev rule for {UDEV_RULE_PATH}:\n\n{}",
+        diagnostics::udev_rule()
+    );
+    if !install {
+        println!("Run with --install to install it.");
+        return;
+    }
+
+    print!("Write {UDEV_RULE_PATH} and reload udev? This needs root. [y/N] ");
+    io::stdout().flush().ok();
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).ok();
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        println!("Not installed.");
+        return;
+    }
+
+    match diagnostics::install_udev_rule() {
+        Ok(()) => println!("Installed. Replug the device or reboot if access doesn't change."),
+        Err(e) => println!("Installing failed: {e}"),
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    callback::{Callback, CallbackThread, DeckSample},
+    config::SteamdeckConfig,
+    thread_config::{ThreadConfig, ThreadPriority},
+    SteamDeckInputError, SteamdeckInput,
+};
+
+#[derive(Default)]
+pub struct SteamdeckInputBuilder {
+    config: SteamdeckConfig,
+    thread: ThreadConfig,
+    callback: Option<(Callback, CallbackThread)>,
+}
+
+impl SteamdeckInputBuilder {
+    pub fn config(mut self, config: SteamdeckConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Name of the input thread, "steamdeck-input" by default.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread.name = Some(name.into());
+        self
+    }
+
+    /// Scheduling priority of the input thread.
+    pub fn priority(mut self, priority: ThreadPriority) -> Self {
+        self.thread.priority = priority;
+        self
+    }
+
+    /// Restricts the input thread to `cpus`.
+    pub fn cpu_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.thread.cpu_affinity = cpus.into_iter().collect();
+        self
+    }
+
+    /// Calls `callback` for every packet, see `SteamdeckInput::with_callback`.
+    pub fn callback(
+        mut self,
+        thread: CallbackThread,
+        callback: impl FnMut(&DeckSample) + Send + 'static,
+    ) -> Self {
+        self.callback = Some((Box::new(callback), thread));
+        self
+    }
+
+    /// Fails if the config doesn't pass `SteamdeckConfig::validate` or a thread can't be
+    /// spawned.
+    pub fn build(self) -> Result<SteamdeckInput, SteamDeckInputError> {
+        SteamdeckInput::spawn(self.config, &self.thread, self.callback)
+    }
+}
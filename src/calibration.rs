@@ -0,0 +1,310 @@
+use crate::protocol::{
+    SteamDeckStatePacket, FEATURE_REPORT_MESSAGE_ID_CALIBRATE_ANALOG,
+    FEATURE_REPORT_MESSAGE_ID_CALIBRATE_ANALOG_TRIGGERS,
+    FEATURE_REPORT_MESSAGE_ID_CALIBRATE_JOYSTICK,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AxisCalibration {
+    pub min: i16,
+    pub center: i16,
+    pub max: i16,
+}
+
+impl Default for AxisCalibration {
+    fn default() -> Self {
+        AxisCalibration {
+            min: -i16::MAX,
+            center: 0,
+            max: i16::MAX,
+        }
+    }
+}
+
+impl AxisCalibration {
+    pub fn normalize(&self, raw: i16) -> f32 {
+        let offset = raw as f32 - self.center as f32;
+        let range = if offset >= 0.0 {
+            self.max as f32 - self.center as f32
+        } else {
+            self.center as f32 - self.min as f32
+        };
+
+        if range <= 0.0 {
+            return 0.0;
+        }
+
+        (offset / range).clamp(-1.0, 1.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TriggerCalibration {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Default for TriggerCalibration {
+    fn default() -> Self {
+        TriggerCalibration {
+            min: 0,
+            max: i16::MAX as u16,
+        }
+    }
+}
+
+impl TriggerCalibration {
+    pub fn normalize(&self, raw: u16) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+
+        ((raw as f32 - self.min as f32) / (self.max as f32 - self.min as f32)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DeviceCalibration {
+    pub left_stick_x: AxisCalibration,
+    pub left_stick_y: AxisCalibration,
+    pub right_stick_x: AxisCalibration,
+    pub right_stick_y: AxisCalibration,
+    pub left_trigger: TriggerCalibration,
+    pub right_trigger: TriggerCalibration,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CalibrationStep {
+    /// Sticks released, triggers released.
+    Center,
+    /// Sticks rotated along their full circle, triggers pulled all the way.
+    Extremes,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Calibrator {
+    step: CalibrationStep,
+    center_sum: [i64; 4],
+    center_samples: i64,
+    trigger_rest: [u16; 2],
+    min: [i16; 4],
+    max: [i16; 4],
+    trigger_max: [u16; 2],
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Calibrator {
+    pub fn new() -> Calibrator {
+        Calibrator {
+            step: CalibrationStep::Center,
+            center_sum: [0; 4],
+            center_samples: 0,
+            trigger_rest: [0; 2],
+            min: [i16::MAX; 4],
+            max: [i16::MIN; 4],
+            trigger_max: [0; 2],
+        }
+    }
+
+    pub fn step(&self) -> CalibrationStep {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: CalibrationStep) {
+        self.step = step;
+    }
+
+    pub fn sample(&mut self, packet: &SteamDeckStatePacket) {
+        let sticks = [
+            packet.left_stick_x,
+            packet.left_stick_y,
+            packet.right_stick_x,
+            packet.right_stick_y,
+        ];
+        let triggers = [packet.trigger_raw_l, packet.trigger_raw_r];
+
+        match self.step {
+            CalibrationStep::Center => {
+                for (sum, value) in self.center_sum.iter_mut().zip(sticks) {
+                    *sum += value as i64;
+                }
+                for (rest, value) in self.trigger_rest.iter_mut().zip(triggers) {
+                    *rest = (*rest).max(value);
+                }
+                self.center_samples += 1;
+            }
+            CalibrationStep::Extremes => {
+                for (i, value) in sticks.into_iter().enumerate() {
+                    self.min[i] = self.min[i].min(value);
+                    self.max[i] = self.max[i].max(value);
+                }
+                for (max, value) in self.trigger_max.iter_mut().zip(triggers) {
+                    *max = (*max).max(value);
+                }
+            }
+        }
+    }
+
+    /// Returns `None` until both steps have seen samples and every axis moved on both
+    /// sides of its center.
+    pub fn finish(&self) -> Option<DeviceCalibration> {
+        if self.center_samples == 0 {
+            return None;
+        }
+
+        let mut axes = [AxisCalibration::default(); 4];
+        for (i, axis) in axes.iter_mut().enumerate() {
+            let center = (self.center_sum[i] / self.center_samples) as i16;
+            if self.min[i] >= center || self.max[i] <= center {
+                return None;
+            }
+            *axis = AxisCalibration {
+                min: self.min[i],
+                center,
+                max: self.max[i],
+            };
+        }
+
+        let mut triggers = [TriggerCalibration::default(); 2];
+        for (i, trigger) in triggers.iter_mut().enumerate() {
+            if self.trigger_max[i] <= self.trigger_rest[i] {
+                return None;
+            }
+            *trigger = TriggerCalibration {
+                min: self.trigger_rest[i],
+                max: self.trigger_max[i],
+            };
+        }
+
+        Some(DeviceCalibration {
+            left_stick_x: axes[0],
+            left_stick_y: axes[1],
+            right_stick_x: axes[2],
+            right_stick_y: axes[3],
+            left_trigger: triggers[0],
+            right_trigger: triggers[1],
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FirmwareCalibration {
+    Joysticks,
+    AnalogTriggers,
+    Analog,
+}
+
+impl FirmwareCalibration {
+    pub(crate) fn report_type(self) -> u8 {
+        match self {
+            FirmwareCalibration::Joysticks => FEATURE_REPORT_MESSAGE_ID_CALIBRATE_JOYSTICK,
+            FirmwareCalibration::AnalogTriggers => {
+                FEATURE_REPORT_MESSAGE_ID_CALIBRATE_ANALOG_TRIGGERS
+            }
+            FirmwareCalibration::Analog => FEATURE_REPORT_MESSAGE_ID_CALIBRATE_ANALOG,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    fn packet(sticks: [i16; 4], triggers: [u16; 2]) -> SteamDeckStatePacket {
+        let mut packet = SteamDeckStatePacket::zeroed();
+        packet.left_stick_x = sticks[0];
+        packet.left_stick_y = sticks[1];
+        packet.right_stick_x = sticks[2];
+        packet.right_stick_y = sticks[3];
+        packet.trigger_raw_l = triggers[0];
+        packet.trigger_raw_r = triggers[1];
+        packet
+    }
+
+    #[test]
+    fn axis_normalizes_each_side_separately() {
+        let axis = AxisCalibration {
+            min: -1000,
+            center: 100,
+            max: 2100,
+        };
+        assert_eq!(axis.normalize(100), 0.0);
+        assert_eq!(axis.normalize(1100), 0.5);
+        assert_eq!(axis.normalize(-450), -0.5);
+        assert_eq!(axis.normalize(i16::MAX), 1.0);
+        assert_eq!(axis.normalize(i16::MIN), -1.0);
+    }
+
+    #[test]
+    fn trigger_normalizes_and_clamps() {
+        let trigger = TriggerCalibration { min: 100, max: 300 };
+        assert_eq!(trigger.normalize(0), 0.0);
+        assert_eq!(trigger.normalize(200), 0.5);
+        assert_eq!(trigger.normalize(1000), 1.0);
+        assert_eq!(TriggerCalibration { min: 5, max: 5 }.normalize(5), 0.0);
+    }
+
+    #[test]
+    fn captures_center_and_range() {
+        let mut calibrator = Calibrator::new();
+        calibrator.sample(&packet([10, -10, 20, 0], [50, 60]));
+        calibrator.sample(&packet([30, -30, 20, 0], [70, 40]));
+
+        calibrator.set_step(CalibrationStep::Extremes);
+        calibrator.sample(&packet([-30000, 31000, -29000, 30000], [30000, 200]));
+        calibrator.sample(&packet([32000, -32000, 28000, -31000], [100, 29000]));
+
+        let calibration = calibrator.finish().unwrap();
+        assert_eq!(
+            calibration.left_stick_x,
+            AxisCalibration {
+                min: -30000,
+                center: 20,
+                max: 32000
+            }
+        );
+        assert_eq!(calibration.left_stick_y.center, -20);
+        assert_eq!(calibration.right_stick_y.min, -31000);
+        // The highest resting value, so a released trigger reads 0
+        assert_eq!(
+            calibration.left_trigger,
+            TriggerCalibration {
+                min: 70,
+                max: 30000
+            }
+        );
+        assert_eq!(calibration.right_trigger.max, 29000);
+    }
+
+    #[test]
+    fn needs_both_steps_and_full_range() {
+        let mut calibrator = Calibrator::new();
+        assert_eq!(calibrator.finish(), None);
+
+        calibrator.sample(&packet([0; 4], [0; 2]));
+        assert_eq!(calibrator.finish(), None);
+
+        // Only one side of each stick
+        calibrator.set_step(CalibrationStep::Extremes);
+        calibrator.sample(&packet([20000; 4], [20000; 2]));
+        assert_eq!(calibrator.finish(), None);
+
+        calibrator.sample(&packet([-20000; 4], [0; 2]));
+        assert!(calibrator.finish().is_some());
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    any::Any,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{atomic::Ordering, mpsc, Arc},
+    thread::JoinHandle,
+};
+
+use crate::{
+    protocol::SteamDeckStatePacket, thread_config::ThreadConfig, GamepadState, InputEvent,
+    SteamdeckShared,
+};
+
+/// What a packet callback sees for every packet.
+#[derive(Clone, Debug)]
+pub struct DeckSample {
+    /// The packet as decoded from the device.
+    pub packet: SteamDeckStatePacket,
+    /// This packet alone, as in `SteamdeckInput::drain_since`.
+    pub state: GamepadState,
+    /// Events produced by this packet. They are also queued for `poll_events`.
+    pub events: Vec<InputEvent>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CallbackThread {
+    /// Inside the input thread right after decoding. Lowest latency, but a slow callback
+    /// delays reading the next packet.
+    #[default]
+    Reader,
+    /// On a thread of its own fed by the input thread, so the callback can't hold up reading.
+    /// A callback that falls behind misses samples rather than building up latency.
+    /// Named "steamdeck-callback" unless configured otherwise.
+    Dedicated(ThreadConfig),
+}
+
+pub(crate) type Callback = Box<dyn FnMut(&DeckSample) + Send>;
+
+/// Samples waiting for a `Dedicated` callback, about a quarter second of packets. Beyond that
+/// new samples are dropped, see `InputStats::callback_drops`.
+const MAX_QUEUED_SAMPLES: usize = 64;
+
+pub(crate) enum CallbackDispatch {
+    Reader(Callback),
+    Dedicated(mpsc::SyncSender<DeckSample>),
+}
+
+impl CallbackDispatch {
+    /// For `Dedicated` also spawns the callback thread, which ends with the input thread.
+    pub fn new(
+        mut callback: Callback,
+        thread: CallbackThread,
+        shared: &Arc<SteamdeckShared>,
+    ) -> io::Result<(CallbackDispatch, Option<JoinHandle<()>>)> {
+        match thread {
+            CallbackThread::Reader => Ok((CallbackDispatch::Reader(callback), None)),
+            CallbackThread::Dedicated(config) => {
+                let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_SAMPLES);
+                let shared = shared.clone();
+                let handle = config.spawn("steamdeck-callback", move || {
+                    for sample in receiver {
+                        invoke(&mut callback, &sample, &shared);
+                    }
+                })?;
+                Ok((CallbackDispatch::Dedicated(sender), Some(handle)))
+            }
+        }
+    }
+
+    pub fn dispatch(&mut self, sample: DeckSample, shared: &SteamdeckShared) {
+        match self {
+            CallbackDispatch::Reader(callback) => invoke(callback, &sample, shared),
+            CallbackDispatch::Dedicated(sender) => {
+                if let Err(mpsc::TrySendError::Full(_)) = sender.try_send(sample) {
+                    shared.callback_drops.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+/// A panicking callback keeps being called; the first panic is logged and all are counted
+/// in `InputStats::callback_panics`.
+fn invoke(callback: &mut Callback, sample: &DeckSample, shared: &SteamdeckShared) {
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| callback(sample))) {
+        if shared.callback_panics.fetch_add(1, Ordering::Relaxed) == 0 {
+            log::error!(
+                "SteamDeck packet callback panicked: {}",
+                panic_message(&*payload)
+            );
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
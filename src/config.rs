@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use crate::{
+    enumerate::{STEAM_DECK_PRODUCT_ID, VALVE_VENDOR_ID},
+    normalization::Normalization,
+    SteamDeckInputError,
+};
+
+/// Well below the time device requests wait for the input thread, so a stall doesn't make
+/// them time out.
+pub const MAX_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LizardMode {
+    /// Leave the firmware's keyboard and mouse emulation alone.
+    Keep,
+    /// Turn it off on connect and again every `keepalive_packets` packets, since the firmware
+    /// turns it back on by itself after a while.
+    Disable {
+        keepalive_packets: u32,
+        /// Restore the default mappings when `SteamdeckInput` is dropped.
+        restore_on_exit: bool,
+    },
+}
+
+impl Default for LizardMode {
+    fn default() -> Self {
+        LizardMode::Disable {
+            keepalive_packets: 200,
+            restore_on_exit: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SteamdeckConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// HID interface carrying the controller reports.
+    pub interface: i32,
+    /// Only open the device with this serial number.
+    pub serial_number: Option<String>,
+    /// How long a read waits for a packet, 1 to 100 ms. An expired wait is counted in
+    /// `InputStats::read_timeouts` and the device stays connected, so short values only cost
+    /// wakeups. Feature reports queued during a stall and dropping `SteamdeckInput` wait for
+    /// the read to return.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "read_timeout_ms", with = "crate::serde_format::millis")
+    )]
+    pub read_timeout: Duration,
+    /// Initial value of `SteamdeckInput::lizard_mode`.
+    pub lizard_mode: LizardMode,
+    /// Initial value of `SteamdeckInput::stale_timeout`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "stale_timeout_ms", with = "crate::serde_format::millis")
+    )]
+    pub stale_timeout: Duration,
+    /// Wait between attempts to find or reopen the device.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "retry_interval_ms", with = "crate::serde_format::millis")
+    )]
+    pub retry_interval: Duration,
+    /// Initial value of `SteamdeckInput::normalization`.
+    pub normalization: Normalization,
+}
+
+impl Default for SteamdeckConfig {
+    fn default() -> Self {
+        SteamdeckConfig {
+            vendor_id: VALVE_VENDOR_ID,
+            product_id: STEAM_DECK_PRODUCT_ID,
+            interface: 2,
+            serial_number: None,
+            read_timeout: Duration::from_millis(16),
+            lizard_mode: LizardMode::default(),
+            stale_timeout: Duration::from_millis(100),
+            retry_interval: Duration::from_millis(1600),
+            normalization: Normalization::default(),
+        }
+    }
+}
+
+impl SteamdeckConfig {
+    pub fn validate(&self) -> Result<(), SteamDeckInputError> {
+        let invalid = |message: &str| Err(SteamDeckInputError::InvalidConfig(message.to_string()));
+
+        if self.read_timeout < Duration::from_millis(1) || self.read_timeout > MAX_READ_TIMEOUT {
+            return invalid("read_timeout must be between 1 ms and 100 ms");
+        }
+        if self.retry_interval.is_zero() {
+            return invalid("retry_interval must not be zero");
+        }
+        if self.stale_timeout.is_zero() {
+            return invalid("stale_timeout must not be zero");
+        }
+        if let LizardMode::Disable {
+            keepalive_packets: 0,
+            ..
+        } = self.lizard_mode
+        {
+            return invalid("lizard_mode keepalive_packets must not be zero");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_timeout_ms(&self) -> i32 {
+        self.read_timeout.as_millis() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_read_timeout(ms: u64) -> SteamdeckConfig {
+        SteamdeckConfig {
+            read_timeout: Duration::from_millis(ms),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn read_timeout_is_bounded() {
+        assert!(SteamdeckConfig::default().validate().is_ok());
+        assert!(with_read_timeout(1).validate().is_ok());
+        assert!(with_read_timeout(100).validate().is_ok());
+        assert!(with_read_timeout(0).validate().is_err());
+        assert!(with_read_timeout(101).validate().is_err());
+        assert!(with_read_timeout(1000).validate().is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    config::SteamdeckConfig,
+    enumerate::{self, ValveDevice, VALVE_VENDOR_ID},
+};
+
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/60-steamdeck-input.rules";
+
+const UDEV_RULE_DIRS: [&str; 3] = [
+    "/etc/udev/rules.d",
+    "/lib/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+];
+
+/// Gives logged in users access to Valve devices, both through hidraw and libusb.
+pub fn udev_rule() -> String {
+    format!(
+        "# Valve HID devices, e.g. the Steam Deck controller\n\
+         KERNEL==\"hidraw*\", ATTRS{{idVendor}}==\"{VALVE_VENDOR_ID:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n\
+         SUBSYSTEM==\"usb\", ATTRS{{idVendor}}==\"{VALVE_VENDOR_ID:04x}\", MODE=\"0660\", TAG+=\"uaccess\"\n"
+    )
+}
+
+/// Writes `udev_rule` to `UDEV_RULE_PATH` and reloads udev. Needs root; only call this
+/// after the user agreed to it.
+pub fn install_udev_rule() -> io::Result<()> {
+    fs::write(UDEV_RULE_PATH, udev_rule())?;
+    for args in [
+        &["control", "--reload-rules"][..],
+        &[
+            "trigger",
+            "--action=add",
+            "--subsystem-match=hidraw",
+            "--subsystem-match=usb",
+        ][..],
+    ] {
+        let status = Command::new("udevadm").args(args).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "udevadm {} failed: {status}",
+                args[0]
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Hidraw,
+    /// The USB device node libusb opens.
+    Usb,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceNode {
+    pub path: PathBuf,
+    pub kind: NodeKind,
+    pub product_id: u16,
+    /// USB interface the hidraw node belongs to.
+    pub interface: Option<i32>,
+    /// Readable and writable by this process.
+    pub accessible: bool,
+    /// Needed to open the diagnosed interface: the USB device node, which the libusb backend
+    /// opens, and the hidraw node of the interface. Only these count as `NoAccess` problems.
+    pub used: bool,
+}
+
+impl DeviceNode {
+    fn used_by(&self, config: &SteamdeckConfig) -> bool {
+        self.product_id == config.product_id
+            && match self.kind {
+                NodeKind::Usb => true,
+                NodeKind::Hidraw => self.interface == Some(config.interface),
+            }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Problem {
+    HidApi(String),
+    NoValveDevice,
+    NoControllerInterface {
+        vendor_id: u16,
+        product_id: u16,
+        interface: i32,
+        serial_number: Option<String>,
+    },
+    NoAccess(PathBuf),
+    OpenFailed {
+        path: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::HidApi(e) => write!(f, "hidapi failed to initialize: {e}"),
+            Problem::NoValveDevice => write!(
+                f,
+                "No Valve ({VALVE_VENDOR_ID:04x}) HID devices found. On a Steam Deck the \
+                 controller should always be present; check `lsusb` and `dmesg`."
+            ),
+            Problem::NoControllerInterface {
+                vendor_id,
+                product_id,
+                interface,
+                serial_number,
+            } => {
+                write!(
+                    f,
+                    "Valve devices are present, but none is {vendor_id:04x}:{product_id:04x} \
+                     with interface {interface}"
+                )?;
+                if let Some(serial_number) = serial_number {
+                    write!(f, " and serial number {serial_number}")?;
+                }
+                write!(
+                    f,
+                    ". Check the vendor_id, product_id, interface and serial_number of \
+                     SteamdeckConfig against the list above."
+                )
+            }
+            Problem::NoAccess(path) => write!(
+                f,
+                "{} is not readable and writable by this user. Install the udev rule (or add \
+                 the user to the group owning it), then replug the device or reboot.",
+                path.display()
+            ),
+            Problem::OpenFailed { path, error } => {
+                write!(f, "Opening the controller interface {path} failed: {error}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Diagnosis {
+    pub interfaces: Vec<ValveDevice>,
+    /// Device nodes of Valve devices, empty where they can't be inspected (outside Linux).
+    pub nodes: Vec<DeviceNode>,
+    /// Installed udev rule files mentioning the Valve vendor id.
+    pub udev_rules: Vec<PathBuf>,
+    pub problems: Vec<Problem>,
+}
+
+impl Diagnosis {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Some device node is inaccessible, which the udev rule fixes.
+    pub fn needs_udev_rule(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|problem| matches!(problem, Problem::NoAccess(_)))
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "HID interfaces:")?;
+        for i in &self.interfaces {
+            writeln!(
+                f,
+                "  {:04x}:{:04x} ({:?}) interface {} at {} (serial {})",
+                i.vendor_id,
+                i.product_id,
+                i.kind,
+                i.interface,
+                i.path,
+                i.serial_number.as_deref().unwrap_or("unknown")
+            )?;
+        }
+        writeln!(f, "Device nodes:")?;
+        for node in &self.nodes {
+            writeln!(
+                f,
+                "  {} ({:?}, product {:04x}, interface {}): {}",
+                node.path.display(),
+                node.kind,
+                node.product_id,
+                node.interface
+                    .map_or("-".to_string(), |interface| interface.to_string()),
+                match (node.accessible, node.used) {
+                    (true, _) => "ok",
+                    (false, true) => "no access",
+                    (false, false) => "no access, not used",
+                }
+            )?;
+        }
+        writeln!(f, "udev rules:")?;
+        for rule in &self.udev_rules {
+            writeln!(f, "  {}", rule.display())?;
+        }
+        if self.is_ok() {
+            writeln!(f, "No problems found.")
+        } else {
+            writeln!(f, "Problems:")?;
+            for problem in &self.problems {
+                writeln!(f, "  - {problem}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Looks for everything that keeps `SteamdeckInput` from opening the device described by
+/// `config`. Briefly opens that interface, so a running `SteamdeckInput` may log an error.
+pub fn diagnose(config: &SteamdeckConfig) -> Diagnosis {
+    let mut diagnosis = Diagnosis {
+        nodes: device_nodes(),
+        udev_rules: installed_udev_rules(),
+        ..Default::default()
+    };
+
+    let api = match hidapi::HidApi::new() {
+        Ok(api) => api,
+        Err(e) => {
+            diagnosis.problems.push(Problem::HidApi(e.to_string()));
+            return diagnosis;
+        }
+    };
+
+    diagnosis.interfaces = enumerate::devices(&api, VALVE_VENDOR_ID);
+
+    for node in &mut diagnosis.nodes {
+        node.used = node.used_by(config);
+        if node.used && !node.accessible {
+            diagnosis
+                .problems
+                .push(Problem::NoAccess(node.path.clone()));
+        }
+    }
+
+    if diagnosis.interfaces.is_empty() && diagnosis.nodes.is_empty() {
+        diagnosis.problems.push(Problem::NoValveDevice);
+        return diagnosis;
+    }
+
+    // The same interface `SteamdeckInput` would open
+    match enumerate::find_device(&api, config) {
+        Some(device) => {
+            if let Err(e) = device.open(&api) {
+                diagnosis.problems.push(Problem::OpenFailed {
+                    path: device.path,
+                    error: e.to_string(),
+                });
+            }
+        }
+        None => diagnosis.problems.push(Problem::NoControllerInterface {
+            vendor_id: config.vendor_id,
+            product_id: config.product_id,
+            interface: config.interface,
+            serial_number: config.serial_number.clone(),
+        }),
+    }
+
+    diagnosis
+}
+
+fn installed_udev_rules() -> Vec<PathBuf> {
+    let vendor = format!("{VALVE_VENDOR_ID:04x}");
+    UDEV_RULE_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            fs::read_to_string(path).is_ok_and(|rules| rules.to_lowercase().contains(&vendor))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn device_nodes() -> Vec<DeviceNode> {
+    let mut nodes: Vec<DeviceNode> = Vec::new();
+    let Ok(entries) = fs::read_dir("/sys/class/hidraw") else {
+        return nodes;
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let device = entry.path().join("device");
+        let Some((vendor_id, product_id)) = fs::read_to_string(device.join("uevent"))
+            .ok()
+            .and_then(|uevent| parse_hid_id(&uevent))
+        else {
+            continue;
+        };
+        if vendor_id != VALVE_VENDOR_ID {
+            continue;
+        }
+
+        // hidraw -> HID device -> USB interface -> USB device
+        let usb_interface = fs::canonicalize(&device)
+            .ok()
+            .and_then(|hid| hid.parent().map(Path::to_path_buf));
+        let interface = usb_interface
+            .as_ref()
+            .and_then(|dir| read_sysfs_number(&dir.join("bInterfaceNumber"), 16));
+
+        let path = Path::new("/dev").join(entry.file_name());
+        nodes.push(DeviceNode {
+            accessible: accessible(&path),
+            path,
+            kind: NodeKind::Hidraw,
+            product_id,
+            interface: interface.map(|i| i as i32),
+            used: false,
+        });
+
+        let usb_device = usb_interface.as_ref().and_then(|dir| dir.parent());
+        if let Some((bus, dev)) = usb_device.and_then(|dir| {
+            Some((
+                read_sysfs_number(&dir.join("busnum"), 10)?,
+                read_sysfs_number(&dir.join("devnum"), 10)?,
+            ))
+        }) {
+            let path = PathBuf::from(format!("/dev/bus/usb/{bus:03}/{dev:03}"));
+            if !nodes.iter().any(|node| node.path == path) {
+                nodes.push(DeviceNode {
+                    accessible: accessible(&path),
+                    path,
+                    kind: NodeKind::Usb,
+                    product_id,
+                    interface: None,
+                    used: false,
+                });
+            }
+        }
+    }
+
+    nodes.sort_by(|a, b| a.path.cmp(&b.path));
+    nodes
+}
+
+#[cfg(not(target_os = "linux"))]
+fn device_nodes() -> Vec<DeviceNode> {
+    Vec::new()
+}
+
+/// `HID_ID=0003:000028DE:00001205` from a HID device's uevent.
+#[cfg(target_os = "linux")]
+fn parse_hid_id(uevent: &str) -> Option<(u16, u16)> {
+    let id = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_ID="))?;
+    let mut parts = id.split(':').skip(1);
+    let vendor_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let product_id = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((vendor_id as u16, product_id as u16))
+}
+
+#[cfg(target_os = "linux")]
+fn read_sysfs_number(path: &Path, radix: u32) -> Option<u32> {
+    u32::from_str_radix(fs::read_to_string(path).ok()?.trim(), radix).ok()
+}
+
+#[cfg(target_os = "linux")]
+fn accessible(path: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(kind: NodeKind, product_id: u16, interface: Option<i32>) -> DeviceNode {
+        DeviceNode {
+            path: PathBuf::new(),
+            kind,
+            product_id,
+            interface,
+            accessible: false,
+            used: false,
+        }
+    }
+
+    #[test]
+    fn only_nodes_of_the_opened_interface_are_used() {
+        let config = SteamdeckConfig::default();
+        let product_id = config.product_id;
+        assert!(node(NodeKind::Usb, product_id, None).used_by(&config));
+        assert!(node(NodeKind::Hidraw, product_id, Some(config.interface)).used_by(&config));
+        assert!(!node(NodeKind::Hidraw, product_id, Some(0)).used_by(&config));
+        assert!(!node(NodeKind::Hidraw, product_id, None).used_by(&config));
+        assert!(!node(NodeKind::Usb, 0x1142, None).used_by(&config));
+    }
+}
@@ -0,0 +1,114 @@
+use std::ffi::CString;
+
+use hidapi::{DeviceInfo, HidApi, HidDevice, HidError, HidResult};
+
+use crate::{config::SteamdeckConfig, SteamDeckInputError};
+
+pub const VALVE_VENDOR_ID: u16 = 0x28de;
+pub const STEAM_DECK_PRODUCT_ID: u16 = 0x1205;
+pub const STEAM_CONTROLLER_WIRED_PRODUCT_ID: u16 = 0x1102;
+pub const STEAM_CONTROLLER_DONGLE_PRODUCT_ID: u16 = 0x1142;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceKind {
+    SteamDeck,
+    SteamControllerWired,
+    /// The wireless receiver; it exposes one interface per controller slot.
+    SteamControllerDongle,
+    Unknown,
+}
+
+impl DeviceKind {
+    pub fn from_product_id(product_id: u16) -> DeviceKind {
+        match product_id {
+            STEAM_DECK_PRODUCT_ID => DeviceKind::SteamDeck,
+            STEAM_CONTROLLER_WIRED_PRODUCT_ID => DeviceKind::SteamControllerWired,
+            STEAM_CONTROLLER_DONGLE_PRODUCT_ID => DeviceKind::SteamControllerDongle,
+            _ => DeviceKind::Unknown,
+        }
+    }
+}
+
+/// One HID interface of a Valve device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValveDevice {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub interface: i32,
+    /// `None` on Linux, where the libusb backend doesn't report usages.
+    pub usage_page: Option<u16>,
+    pub usage: Option<u16>,
+    pub serial_number: Option<String>,
+    pub path: String,
+    pub kind: DeviceKind,
+}
+
+impl ValveDevice {
+    fn from_device_info(device_info: &DeviceInfo) -> ValveDevice {
+        ValveDevice {
+            vendor_id: device_info.vendor_id(),
+            product_id: device_info.product_id(),
+            interface: device_info.interface_number(),
+            #[cfg(not(target_os = "linux"))]
+            usage_page: Some(device_info.usage_page()),
+            #[cfg(target_os = "linux")]
+            usage_page: None,
+            #[cfg(not(target_os = "linux"))]
+            usage: Some(device_info.usage()),
+            #[cfg(target_os = "linux")]
+            usage: None,
+            serial_number: device_info.serial_number().map(|s| s.to_string()),
+            path: device_info.path().to_string_lossy().into_owned(),
+            kind: DeviceKind::from_product_id(device_info.product_id()),
+        }
+    }
+
+    /// `config` changed to open exactly this interface.
+    pub fn select(&self, config: SteamdeckConfig) -> SteamdeckConfig {
+        SteamdeckConfig {
+            vendor_id: self.vendor_id,
+            product_id: self.product_id,
+            interface: self.interface,
+            serial_number: self.serial_number.clone(),
+            ..config
+        }
+    }
+
+    pub fn matches(&self, config: &SteamdeckConfig) -> bool {
+        self.vendor_id == config.vendor_id
+            && self.product_id == config.product_id
+            && self.interface == config.interface
+            && config
+                .serial_number
+                .as_ref()
+                .is_none_or(|wanted| self.serial_number.as_ref() == Some(wanted))
+    }
+
+    pub(crate) fn open(&self, api: &HidApi) -> HidResult<HidDevice> {
+        let path = CString::new(self.path.as_str()).map_err(|e| HidError::HidApiError {
+            message: e.to_string(),
+        })?;
+        api.open_path(&path)
+    }
+}
+
+/// All HID interfaces with the Valve vendor id, in hidapi's order.
+pub fn enumerate() -> Result<Vec<ValveDevice>, SteamDeckInputError> {
+    Ok(devices(&HidApi::new()?, VALVE_VENDOR_ID))
+}
+
+pub(crate) fn devices(api: &HidApi, vendor_id: u16) -> Vec<ValveDevice> {
+    api.device_list()
+        .filter(|device_info| device_info.vendor_id() == vendor_id)
+        .map(ValveDevice::from_device_info)
+        .collect()
+}
+
+/// The interface `SteamdeckInput` opens for `config`: the last one matching it.
+pub(crate) fn find_device(api: &HidApi, config: &SteamdeckConfig) -> Option<ValveDevice> {
+    devices(api, config.vendor_id)
+        .into_iter()
+        .rfind(|device| device.matches(config))
+}
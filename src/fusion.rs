@@ -0,0 +1,360 @@
+use std::ops::Mul;
+
+use crate::protocol::SteamDeckStatePacket;
+
+// The Deck IMU runs at +-2 g and +-2000 deg/s.
+const ACCEL_G_PER_COUNT: f32 = 1.0 / 16384.0;
+const GYRO_DEG_PER_SEC_PER_COUNT: f32 = 2000.0 / 32768.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    pub fn from_yaw(yaw: f32) -> Quaternion {
+        let (sin, cos) = (yaw * 0.5).sin_cos();
+        Quaternion {
+            w: cos,
+            x: 0.0,
+            y: 0.0,
+            z: sin,
+        }
+    }
+
+    pub fn normalized(self) -> Quaternion {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if norm == 0.0 {
+            return Quaternion::IDENTITY;
+        }
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Roll (x), pitch (y) and yaw (z) in radians.
+    pub fn to_euler(&self) -> EulerAngles {
+        let Quaternion { w, x, y, z } = *self;
+
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+
+        EulerAngles { roll, pitch, yaw }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EulerAngles {
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FusionFilter {
+    Madgwick { beta: f32 },
+    Mahony { kp: f32, ki: f32 },
+}
+
+impl Default for FusionFilter {
+    fn default() -> Self {
+        FusionFilter::Madgwick { beta: 0.1 }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ImuSample {
+    /// Acceleration in g.
+    pub accel: [f32; 3],
+    /// Angular velocity in rad/s.
+    pub gyro: [f32; 3],
+}
+
+impl ImuSample {
+    pub fn from_packet(packet: &SteamDeckStatePacket) -> ImuSample {
+        let gyro_scale = GYRO_DEG_PER_SEC_PER_COUNT.to_radians();
+        ImuSample {
+            accel: [
+                packet.accel_x as f32 * ACCEL_G_PER_COUNT,
+                packet.accel_y as f32 * ACCEL_G_PER_COUNT,
+                packet.accel_z as f32 * ACCEL_G_PER_COUNT,
+            ],
+            gyro: [
+                packet.gyro_x as f32 * gyro_scale,
+                packet.gyro_y as f32 * gyro_scale,
+                packet.gyro_z as f32 * gyro_scale,
+            ],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct OrientationFilter {
+    filter: FusionFilter,
+    q: Quaternion,
+    integral_error: [f32; 3],
+    heading: Quaternion,
+}
+
+impl Default for OrientationFilter {
+    fn default() -> Self {
+        Self::new(FusionFilter::default())
+    }
+}
+
+impl OrientationFilter {
+    pub fn new(filter: FusionFilter) -> OrientationFilter {
+        OrientationFilter {
+            filter,
+            q: Quaternion::IDENTITY,
+            integral_error: [0.0; 3],
+            heading: Quaternion::IDENTITY,
+        }
+    }
+
+    pub fn filter(&self) -> FusionFilter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: FusionFilter) {
+        self.filter = filter;
+        self.integral_error = [0.0; 3];
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        (self.heading * self.q).normalized()
+    }
+
+    /// Makes the current heading the new zero yaw, keeping roll and pitch.
+    pub fn reset_heading(&mut self) {
+        self.heading = Quaternion::from_yaw(-self.q.to_euler().yaw);
+    }
+
+    pub fn update(&mut self, sample: &ImuSample, dt: f32) {
+        match self.filter {
+            FusionFilter::Madgwick { beta } => self.update_madgwick(sample, dt, beta),
+            FusionFilter::Mahony { kp, ki } => self.update_mahony(sample, dt, kp, ki),
+        }
+    }
+
+    fn update_madgwick(&mut self, sample: &ImuSample, dt: f32, beta: f32) {
+        let Quaternion {
+            w: q0,
+            x: q1,
+            y: q2,
+            z: q3,
+        } = self.q;
+        let [gx, gy, gz] = sample.gyro;
+
+        let mut q_dot = [
+            0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+            0.5 * (q0 * gx + q2 * gz - q3 * gy),
+            0.5 * (q0 * gy - q1 * gz + q3 * gx),
+            0.5 * (q0 * gz + q1 * gy - q2 * gx),
+        ];
+
+        if let Some([ax, ay, az]) = normalized(sample.accel) {
+            // Gradient descent step on the gravity direction error.
+            let f = [
+                2.0 * (q1 * q3 - q0 * q2) - ax,
+                2.0 * (q0 * q1 + q2 * q3) - ay,
+                2.0 * (0.5 - q1 * q1 - q2 * q2) - az,
+            ];
+            let step = [
+                -2.0 * q2 * f[0] + 2.0 * q1 * f[1],
+                2.0 * q3 * f[0] + 2.0 * q0 * f[1] - 4.0 * q1 * f[2],
+                -2.0 * q0 * f[0] + 2.0 * q3 * f[1] - 4.0 * q2 * f[2],
+                2.0 * q1 * f[0] + 2.0 * q2 * f[1],
+            ];
+            let norm = step.iter().map(|s| s * s).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                for (q, s) in q_dot.iter_mut().zip(step) {
+                    *q -= beta * s / norm;
+                }
+            }
+        }
+
+        self.q = Quaternion {
+            w: q0 + q_dot[0] * dt,
+            x: q1 + q_dot[1] * dt,
+            y: q2 + q_dot[2] * dt,
+            z: q3 + q_dot[3] * dt,
+        }
+        .normalized();
+    }
+
+    fn update_mahony(&mut self, sample: &ImuSample, dt: f32, kp: f32, ki: f32) {
+        let Quaternion {
+            w: q0,
+            x: q1,
+            y: q2,
+            z: q3,
+        } = self.q;
+        let [mut gx, mut gy, mut gz] = sample.gyro;
+
+        if let Some([ax, ay, az]) = normalized(sample.accel) {
+            // Estimated gravity direction vs measured one.
+            let vx = 2.0 * (q1 * q3 - q0 * q2);
+            let vy = 2.0 * (q0 * q1 + q2 * q3);
+            let vz = q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3;
+            let error = [ay * vz - az * vy, az * vx - ax * vz, ax * vy - ay * vx];
+
+            if ki > 0.0 {
+                for (integral, e) in self.integral_error.iter_mut().zip(error) {
+                    *integral += ki * e * dt;
+                }
+            } else {
+                self.integral_error = [0.0; 3];
+            }
+
+            gx += kp * error[0] + self.integral_error[0];
+            gy += kp * error[1] + self.integral_error[1];
+            gz += kp * error[2] + self.integral_error[2];
+        }
+
+        let half_dt = 0.5 * dt;
+        self.q = Quaternion {
+            w: q0 + (-q1 * gx - q2 * gy - q3 * gz) * half_dt,
+            x: q1 + (q0 * gx + q2 * gz - q3 * gy) * half_dt,
+            y: q2 + (q0 * gy - q1 * gz + q3 * gx) * half_dt,
+            z: q3 + (q0 * gz + q1 * gy - q2 * gx) * half_dt,
+        }
+        .normalized();
+    }
+}
+
+fn normalized(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if norm > 0.0 {
+        Some([v[0] / norm, v[1] / norm, v[2] / norm])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    const DT: f32 = 1.0 / 250.0;
+
+    fn run(filter: &mut OrientationFilter, sample: ImuSample, seconds: f32) {
+        for _ in 0..(seconds / DT) as usize {
+            filter.update(&sample, DT);
+        }
+    }
+
+    fn rolled(roll: f32) -> ImuSample {
+        ImuSample {
+            accel: [0.0, roll.sin(), roll.cos()],
+            gyro: [0.0; 3],
+        }
+    }
+
+    const FILTERS: [FusionFilter; 2] = [
+        FusionFilter::Madgwick { beta: 0.1 },
+        FusionFilter::Mahony { kp: 1.0, ki: 0.0 },
+    ];
+
+    #[test]
+    fn packet_units() {
+        let mut packet = SteamDeckStatePacket::zeroed();
+        packet.accel_z = 16384;
+        packet.gyro_x = 16384;
+        let sample = ImuSample::from_packet(&packet);
+        assert_eq!(sample.accel, [0.0, 0.0, 1.0]);
+        assert!((sample.gyro[0] - 1000f32.to_radians()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn integrates_gyro_without_gravity() {
+        for filter in FILTERS {
+            let mut orientation = OrientationFilter::new(filter);
+            let spin = ImuSample {
+                accel: [0.0; 3],
+                gyro: [0.0, 0.0, 1.0],
+            };
+            run(&mut orientation, spin, 1.0);
+            let yaw = orientation.orientation().to_euler().yaw;
+            assert!((yaw - 1.0).abs() < 0.01, "{filter:?}: yaw {yaw}");
+        }
+    }
+
+    #[test]
+    fn converges_to_gravity() {
+        for filter in FILTERS {
+            let mut orientation = OrientationFilter::new(filter);
+            run(&mut orientation, rolled(0.5), 30.0);
+            let euler = orientation.orientation().to_euler();
+            assert!((euler.roll - 0.5).abs() < 0.01, "{filter:?}: {euler:?}");
+            assert!(euler.pitch.abs() < 0.01, "{filter:?}: {euler:?}");
+        }
+    }
+
+    #[test]
+    fn mahony_integral_cancels_gyro_bias() {
+        let mut orientation = OrientationFilter::new(FusionFilter::Mahony { kp: 1.0, ki: 0.5 });
+        let biased = ImuSample {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [0.05, 0.0, 0.0],
+        };
+        run(&mut orientation, biased, 60.0);
+        let roll = orientation.orientation().to_euler().roll;
+        assert!(roll.abs() < 0.01, "roll {roll}");
+    }
+
+    #[test]
+    fn reset_heading_keeps_tilt() {
+        let mut orientation = OrientationFilter::new(FusionFilter::default());
+        run(&mut orientation, rolled(0.3), 30.0);
+        let spin = ImuSample {
+            gyro: [0.0, 0.0, 1.0],
+            ..rolled(0.3)
+        };
+        run(&mut orientation, spin, 0.5);
+        assert!(orientation.orientation().to_euler().yaw.abs() > 0.2);
+
+        orientation.reset_heading();
+        let euler = orientation.orientation().to_euler();
+        assert!(euler.yaw.abs() < 0.01, "{euler:?}");
+        assert!((euler.roll - 0.3).abs() < 0.05, "{euler:?}");
+    }
+}
@@ -0,0 +1,389 @@
+use std::time::{Duration, Instant};
+
+use crate::trackpad::TrackpadSample;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GestureConfig {
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "tap_max_duration_ms", with = "crate::serde_format::millis")
+    )]
+    pub tap_max_duration: Duration,
+    /// Pad widths the finger may travel and still count as a tap or long press.
+    pub tap_max_distance: f32,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "double_tap_max_interval_ms",
+            with = "crate::serde_format::millis"
+        )
+    )]
+    pub double_tap_max_interval: Duration,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "long_press_duration_ms",
+            with = "crate::serde_format::millis"
+        )
+    )]
+    pub long_press_duration: Duration,
+    /// Pad widths.
+    pub swipe_min_distance: f32,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "swipe_max_duration_ms", with = "crate::serde_format::millis")
+    )]
+    pub swipe_max_duration: Duration,
+    /// Raw `pressure_pad_*` value for a force press; it re-arms below 80% of it.
+    pub force_threshold: u16,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            tap_max_duration: Duration::from_millis(200),
+            tap_max_distance: 0.05,
+            double_tap_max_interval: Duration::from_millis(300),
+            long_press_duration: Duration::from_millis(500),
+            swipe_min_distance: 0.3,
+            swipe_max_duration: Duration::from_millis(500),
+            force_threshold: 16000,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SwipeDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Gesture {
+    /// Also reported for the first tap of a double tap.
+    Tap,
+    DoubleTap,
+    LongPress,
+    Swipe {
+        direction: SwipeDirection,
+        /// Pad widths per second, positive y down.
+        velocity: [f32; 2],
+    },
+    ForcePress {
+        pressure: u16,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Touch {
+    start_time: Instant,
+    start: [f32; 2],
+    last: [f32; 2],
+    max_distance: f32,
+    long_press: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    touch: Option<Touch>,
+    last_tap: Option<Instant>,
+    force_pressed: bool,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> GestureRecognizer {
+        GestureRecognizer {
+            config,
+            touch: None,
+            last_tap: None,
+            force_pressed: false,
+        }
+    }
+
+    pub fn config(&self) -> GestureConfig {
+        self.config
+    }
+
+    /// `time` is the receive time of the packet `sample` came from.
+    pub fn update(&mut self, sample: &TrackpadSample, time: Instant, gestures: &mut Vec<Gesture>) {
+        let config = &self.config;
+
+        if sample.pressure >= config.force_threshold {
+            if !self.force_pressed {
+                self.force_pressed = true;
+                gestures.push(Gesture::ForcePress {
+                    pressure: sample.pressure,
+                });
+            }
+        } else if (sample.pressure as u32) * 5 < (config.force_threshold as u32) * 4 {
+            self.force_pressed = false;
+        }
+
+        if sample.touched {
+            let touch = self.touch.get_or_insert(Touch {
+                start_time: time,
+                start: sample.position,
+                last: sample.position,
+                max_distance: 0.0,
+                long_press: false,
+            });
+
+            touch.last = sample.position;
+            touch.max_distance = touch
+                .max_distance
+                .max(distance(touch.start, sample.position));
+
+            if !touch.long_press
+                && touch.max_distance <= config.tap_max_distance
+                && time.duration_since(touch.start_time) >= config.long_press_duration
+            {
+                touch.long_press = true;
+                gestures.push(Gesture::LongPress);
+            }
+            return;
+        }
+
+        let Some(touch) = self.touch.take() else {
+            return;
+        };
+        if touch.long_press {
+            return;
+        }
+
+        let duration = time.duration_since(touch.start_time);
+        if duration <= config.tap_max_duration && touch.max_distance <= config.tap_max_distance {
+            let double = self
+                .last_tap
+                .is_some_and(|last| time.duration_since(last) <= config.double_tap_max_interval);
+            if double {
+                self.last_tap = None;
+                gestures.push(Gesture::DoubleTap);
+            } else {
+                self.last_tap = Some(time);
+                gestures.push(Gesture::Tap);
+            }
+            return;
+        }
+
+        let moved = [
+            (touch.last[0] - touch.start[0]) * 0.5,
+            (touch.last[1] - touch.start[1]) * 0.5,
+        ];
+        if duration <= config.swipe_max_duration
+            && (moved[0] * moved[0] + moved[1] * moved[1]).sqrt() >= config.swipe_min_distance
+        {
+            let direction = if moved[0].abs() > moved[1].abs() {
+                if moved[0] > 0.0 {
+                    SwipeDirection::Right
+                } else {
+                    SwipeDirection::Left
+                }
+            } else if moved[1] > 0.0 {
+                SwipeDirection::Down
+            } else {
+                SwipeDirection::Up
+            };
+
+            let secs = duration.as_secs_f32().max(f32::EPSILON);
+            gestures.push(Gesture::Swipe {
+                direction,
+                velocity: [moved[0] / secs, moved[1] / secs],
+            });
+        }
+    }
+}
+
+// In pad widths; positions span 2 units per width.
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    let dx = (b[0] - a[0]) * 0.5;
+    let dy = (b[1] - a[1]) * 0.5;
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(x: f32, y: f32) -> TrackpadSample {
+        TrackpadSample {
+            touched: true,
+            position: [x, y],
+            pressure: 0,
+        }
+    }
+
+    fn release() -> TrackpadSample {
+        TrackpadSample {
+            touched: false,
+            position: [0.0, 0.0],
+            pressure: 0,
+        }
+    }
+
+    fn pressure(pressure: u16) -> TrackpadSample {
+        TrackpadSample {
+            pressure,
+            ..release()
+        }
+    }
+
+    /// Feeds `samples` at the given millisecond offsets and collects the gestures.
+    fn run(
+        recognizer: &mut GestureRecognizer,
+        start: Instant,
+        samples: &[(u64, TrackpadSample)],
+    ) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+        for (ms, sample) in samples {
+            recognizer.update(sample, start + Duration::from_millis(*ms), &mut gestures);
+        }
+        gestures
+    }
+
+    #[test]
+    fn tap_and_double_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let t = Instant::now();
+
+        let gestures = run(
+            &mut recognizer,
+            t,
+            &[
+                (0, touch(0.1, 0.1)),
+                (50, touch(0.11, 0.1)),
+                (100, release()),
+                (200, touch(0.1, 0.1)),
+                (250, release()),
+                (1000, touch(0.1, 0.1)),
+                (1050, release()),
+            ],
+        );
+        assert_eq!(gestures, [Gesture::Tap, Gesture::DoubleTap, Gesture::Tap]);
+    }
+
+    #[test]
+    fn slow_second_tap_is_a_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let gestures = run(
+            &mut recognizer,
+            Instant::now(),
+            &[
+                (0, touch(0.0, 0.0)),
+                (50, release()),
+                (500, touch(0.0, 0.0)),
+                (550, release()),
+            ],
+        );
+        assert_eq!(gestures, [Gesture::Tap, Gesture::Tap]);
+    }
+
+    #[test]
+    fn long_press_replaces_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let gestures = run(
+            &mut recognizer,
+            Instant::now(),
+            &[
+                (0, touch(0.0, 0.0)),
+                (499, touch(0.0, 0.0)),
+                (500, touch(0.0, 0.0)),
+                (800, touch(0.0, 0.0)),
+                (900, release()),
+            ],
+        );
+        assert_eq!(gestures, [Gesture::LongPress]);
+    }
+
+    #[test]
+    fn swipe_direction_and_velocity() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let gestures = run(
+            &mut recognizer,
+            Instant::now(),
+            &[
+                (0, touch(-0.5, 0.0)),
+                (100, touch(0.1, 0.05)),
+                (200, touch(0.5, 0.1)),
+                (200, release()),
+            ],
+        );
+        // One pad width right and 0.05 down in 0.2 s
+        let [Gesture::Swipe {
+            direction: SwipeDirection::Right,
+            velocity,
+        }] = gestures[..]
+        else {
+            panic!("expected a swipe right, got {gestures:?}");
+        };
+        assert!((velocity[0] - 2.5).abs() < 1e-3);
+        assert!((velocity[1] - 0.25).abs() < 1e-3);
+
+        let gestures = run(
+            &mut recognizer,
+            Instant::now(),
+            &[
+                (0, touch(0.0, 0.5)),
+                (100, touch(0.0, -0.5)),
+                (150, release()),
+            ],
+        );
+        assert!(matches!(
+            gestures[..],
+            [Gesture::Swipe {
+                direction: SwipeDirection::Up,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn slow_or_short_moves_are_not_swipes() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let t = Instant::now();
+        let gestures = run(
+            &mut recognizer,
+            t,
+            &[
+                (0, touch(-0.5, 0.0)),
+                (300, touch(0.5, 0.0)),
+                (600, release()),
+                (1000, touch(0.0, 0.0)),
+                (1100, touch(0.2, 0.0)),
+                (1300, release()),
+            ],
+        );
+        assert_eq!(gestures, []);
+    }
+
+    #[test]
+    fn force_press_rearms_with_hysteresis() {
+        let mut recognizer = GestureRecognizer::new(GestureConfig::default());
+        let gestures = run(
+            &mut recognizer,
+            Instant::now(),
+            &[
+                (0, pressure(16000)),
+                (4, pressure(20000)),
+                // Above 80% of the threshold, still pressed
+                (8, pressure(13000)),
+                (12, pressure(16500)),
+                (16, pressure(12000)),
+                (20, pressure(17000)),
+            ],
+        );
+        assert_eq!(
+            gestures,
+            [
+                Gesture::ForcePress { pressure: 16000 },
+                Gesture::ForcePress { pressure: 17000 }
+            ]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use crate::fusion::ImuSample;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GyroOutput {
+    #[default]
+    Off,
+    /// Angular velocity deflects `GamepadState::gyro_stick`.
+    Stick,
+    /// Rotation is accumulated into `GamepadState::mouse_delta`.
+    Mouse,
+}
+
+/// `buttons` are masks of the `protocol::BUTTON_*` bits, any of which counts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GyroActivation {
+    #[default]
+    Always,
+    WhileHeld(#[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))] u64),
+    WhileReleased(
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))] u64,
+    ),
+    Toggle(#[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))] u64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct GyroAimConfig {
+    pub output: GyroOutput,
+    pub activation: GyroActivation,
+    /// Mouse counts per degree, or stick deflection per degree/s.
+    pub sensitivity: f32,
+    /// Extra sensitivity multiplier reached at `acceleration_threshold`, ramping from none at rest.
+    pub acceleration: f32,
+    /// Degrees/s.
+    pub acceleration_threshold: f32,
+    /// Rotation slower than this (degrees/s) is scaled down towards zero to hide hand tremor.
+    pub tightening: f32,
+    /// Rotation slower than this (degrees/s) is averaged over `smoothing_time`.
+    pub smoothing_threshold: f32,
+    /// Seconds.
+    pub smoothing_time: f32,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl Default for GyroAimConfig {
+    fn default() -> Self {
+        GyroAimConfig {
+            output: GyroOutput::Off,
+            activation: GyroActivation::Always,
+            sensitivity: 1.0,
+            acceleration: 0.0,
+            acceleration_threshold: 180.0,
+            tightening: 0.0,
+            smoothing_threshold: 0.0,
+            smoothing_time: 0.05,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GyroAimOutput {
+    pub stick: [f32; 2],
+    pub mouse: [f32; 2],
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GyroAim {
+    config: GyroAimConfig,
+    toggled: bool,
+    previous_buttons: u64,
+    smoothed: [f32; 2],
+}
+
+impl GyroAim {
+    pub fn new(config: GyroAimConfig) -> GyroAim {
+        GyroAim {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> GyroAimConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: GyroAimConfig) {
+        *self = GyroAim::new(config);
+    }
+
+    pub fn is_active(&self, buttons: u64) -> bool {
+        match self.config.activation {
+            GyroActivation::Always => true,
+            GyroActivation::WhileHeld(mask) => buttons & mask != 0,
+            GyroActivation::WhileReleased(mask) => buttons & mask == 0,
+            GyroActivation::Toggle(_) => self.toggled,
+        }
+    }
+
+    pub fn update(&mut self, buttons: u64, sample: &ImuSample, dt: f32) -> GyroAimOutput {
+        if let GyroActivation::Toggle(mask) = self.config.activation {
+            if buttons & mask != 0 && self.previous_buttons & mask == 0 {
+                self.toggled = !self.toggled;
+            }
+        }
+        self.previous_buttons = buttons;
+
+        if self.config.output == GyroOutput::Off || !self.is_active(buttons) {
+            self.smoothed = [0.0; 2];
+            return GyroAimOutput::default();
+        }
+
+        let config = &self.config;
+
+        // Yaw turns horizontally, pitch vertically; degrees/s
+        let mut rate = [-sample.gyro[1].to_degrees(), -sample.gyro[0].to_degrees()];
+        if config.invert_x {
+            rate[0] = -rate[0];
+        }
+        if config.invert_y {
+            rate[1] = -rate[1];
+        }
+
+        let speed = magnitude(rate);
+
+        if config.smoothing_threshold > 0.0 {
+            let alpha = if config.smoothing_time > 0.0 {
+                dt / (config.smoothing_time + dt)
+            } else {
+                1.0
+            };
+            for (smoothed, r) in self.smoothed.iter_mut().zip(rate) {
+                *smoothed += (r - *smoothed) * alpha;
+            }
+
+            let direct = (speed / config.smoothing_threshold).clamp(0.0, 1.0);
+            for (r, smoothed) in rate.iter_mut().zip(self.smoothed) {
+                *r = *r * direct + smoothed * (1.0 - direct);
+            }
+        }
+
+        if config.tightening > 0.0 && speed < config.tightening {
+            let scale = speed / config.tightening;
+            for r in &mut rate {
+                *r *= scale;
+            }
+        }
+
+        let mut sensitivity = config.sensitivity;
+        if config.acceleration_threshold > 0.0 {
+            let ramp = (speed / config.acceleration_threshold).clamp(0.0, 1.0);
+            sensitivity *= 1.0 + config.acceleration * ramp;
+        }
+
+        let mut output = GyroAimOutput::default();
+        match config.output {
+            GyroOutput::Off => {}
+            GyroOutput::Stick => {
+                let mut stick = [rate[0] * sensitivity, rate[1] * sensitivity];
+                let length = magnitude(stick);
+                if length > 1.0 {
+                    stick = [stick[0] / length, stick[1] / length];
+                }
+                output.stick = stick;
+            }
+            GyroOutput::Mouse => {
+                output.mouse = [rate[0] * sensitivity * dt, rate[1] * sensitivity * dt];
+            }
+        }
+        output
+    }
+}
+
+fn magnitude(v: [f32; 2]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUTTON: u64 = 1 << 3;
+
+    /// Turning right and up at the given degrees/s.
+    fn turning(right: f32, up: f32) -> ImuSample {
+        ImuSample {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [-up.to_radians(), -right.to_radians(), 0.0],
+        }
+    }
+
+    fn aim(config: GyroAimConfig) -> GyroAim {
+        GyroAim::new(GyroAimConfig {
+            output: GyroOutput::Mouse,
+            ..config
+        })
+    }
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4
+    }
+
+    #[test]
+    fn mouse_counts_per_degree() {
+        let mut aim = aim(GyroAimConfig {
+            sensitivity: 2.0,
+            ..Default::default()
+        });
+        let output = aim.update(0, &turning(100.0, -50.0), 0.01);
+        assert!(close(output.mouse, [2.0, -1.0]), "{output:?}");
+        assert_eq!(output.stick, [0.0; 2]);
+    }
+
+    #[test]
+    fn stick_is_clamped_to_unit_circle() {
+        let mut aim = GyroAim::new(GyroAimConfig {
+            output: GyroOutput::Stick,
+            sensitivity: 0.01,
+            ..Default::default()
+        });
+        let output = aim.update(0, &turning(50.0, 0.0), 0.004);
+        assert!(close(output.stick, [0.5, 0.0]), "{output:?}");
+        let output = aim.update(0, &turning(300.0, 400.0), 0.004);
+        assert!(close(output.stick, [0.6, 0.8]), "{output:?}");
+    }
+
+    #[test]
+    fn activation() {
+        let sample = turning(100.0, 0.0);
+
+        let mut held = aim(GyroAimConfig {
+            activation: GyroActivation::WhileHeld(BUTTON),
+            ..Default::default()
+        });
+        assert_eq!(held.update(0, &sample, 0.01).mouse, [0.0; 2]);
+        assert_ne!(held.update(BUTTON, &sample, 0.01).mouse, [0.0; 2]);
+
+        let mut released = aim(GyroAimConfig {
+            activation: GyroActivation::WhileReleased(BUTTON),
+            ..Default::default()
+        });
+        assert_ne!(released.update(0, &sample, 0.01).mouse, [0.0; 2]);
+        assert_eq!(released.update(BUTTON, &sample, 0.01).mouse, [0.0; 2]);
+
+        let mut toggle = aim(GyroAimConfig {
+            activation: GyroActivation::Toggle(BUTTON),
+            ..Default::default()
+        });
+        assert_eq!(toggle.update(0, &sample, 0.01).mouse, [0.0; 2]);
+        assert_ne!(toggle.update(BUTTON, &sample, 0.01).mouse, [0.0; 2]);
+        assert_ne!(toggle.update(0, &sample, 0.01).mouse, [0.0; 2]);
+        assert_eq!(toggle.update(BUTTON, &sample, 0.01).mouse, [0.0; 2]);
+    }
+
+    #[test]
+    fn tightening_scales_slow_rotation() {
+        let mut aim = aim(GyroAimConfig {
+            tightening: 10.0,
+            ..Default::default()
+        });
+        // Half the threshold is scaled by half
+        let output = aim.update(0, &turning(5.0, 0.0), 1.0);
+        assert!(close(output.mouse, [2.5, 0.0]), "{output:?}");
+        let output = aim.update(0, &turning(20.0, 0.0), 1.0);
+        assert!(close(output.mouse, [20.0, 0.0]), "{output:?}");
+    }
+
+    #[test]
+    fn acceleration_ramps_sensitivity() {
+        let mut aim = aim(GyroAimConfig {
+            acceleration: 1.0,
+            acceleration_threshold: 100.0,
+            ..Default::default()
+        });
+        let output = aim.update(0, &turning(50.0, 0.0), 1.0);
+        assert!(close(output.mouse, [75.0, 0.0]), "{output:?}");
+        let output = aim.update(0, &turning(200.0, 0.0), 1.0);
+        assert!(close(output.mouse, [400.0, 0.0]), "{output:?}");
+    }
+
+    #[test]
+    fn inversion() {
+        let mut aim = aim(GyroAimConfig {
+            invert_x: true,
+            invert_y: true,
+            ..Default::default()
+        });
+        let output = aim.update(0, &turning(10.0, 20.0), 1.0);
+        assert!(close(output.mouse, [-10.0, -20.0]), "{output:?}");
+    }
+}
@@ -1,127 +1,1359 @@
 use std::{
-    mem,
+    collections::{HashMap, VecDeque},
+    io, mem,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        mpsc, Arc, Mutex,
     },
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 
-use bytemuck::{from_bytes, from_bytes_mut};
+use builder::SteamdeckInputBuilder;
+use bytemuck::{bytes_of, from_bytes, from_bytes_mut, Zeroable};
+use calibration::{CalibrationStep, Calibrator, DeviceCalibration, FirmwareCalibration};
+use callback::{Callback, CallbackDispatch, CallbackThread, DeckSample};
+use config::{LizardMode, SteamdeckConfig};
+use fusion::{FusionFilter, ImuSample, OrientationFilter, Quaternion};
+use gesture::{Gesture, GestureConfig, GestureRecognizer};
+use gyro::{GyroAim, GyroAimConfig};
 use hidapi::{HidDevice, HidError, HidResult};
+use mapping::{ButtonMapper, ButtonMapping};
+use normalization::Normalization;
+use pad_modes::{TrackpadMode, TrackpadModeState};
 use protocol::{
-    DigitalMapping, FeatureReportMsg, SteamDeckStatePacket, ValveInReport, BUTTON_A, BUTTON_B,
-    BUTTON_DPAD_DOWN, BUTTON_DPAD_LEFT, BUTTON_DPAD_RIGHT, BUTTON_DPAD_UP, BUTTON_L4, BUTTON_L5,
-    BUTTON_LEFT_BUMPER, BUTTON_LEFT_PAD, BUTTON_LEFT_STICK, BUTTON_MENU, BUTTON_QUICK_ACCESS,
-    BUTTON_R4, BUTTON_R5, BUTTON_RIGHT_BUMPER, BUTTON_RIGHT_PAD, BUTTON_RIGHT_STICK, BUTTON_STEAM,
-    BUTTON_VIEW, BUTTON_X, BUTTON_Y, FEATURE_REPORT_MESSAGE_ID_CLEAR_DIGITAL_MAPPINGS,
-    FEATURE_REPORT_MESSAGE_ID_SET_DIGITAL_MAPPINGS, HID_FEATURE_REPORT_BYTES,
+    DigitalMapping, FeatureReportHeader, FeatureReportMsg, MsgFireHapticPulse, MsgTrackpadRequest,
+    SteamDeckStatePacket, ValveControllerRawTrackpadImage, ValveControllerTrackpadImage,
+    ValveInReport, BUTTON_A, BUTTON_B, BUTTON_DPAD_DOWN, BUTTON_DPAD_LEFT, BUTTON_DPAD_RIGHT,
+    BUTTON_DPAD_UP, BUTTON_L4, BUTTON_L5, BUTTON_LEFT_BUMPER, BUTTON_LEFT_PAD, BUTTON_LEFT_STICK,
+    BUTTON_LEFT_TRIGGER, BUTTON_MENU, BUTTON_QUICK_ACCESS, BUTTON_R4, BUTTON_R5,
+    BUTTON_RIGHT_BUMPER, BUTTON_RIGHT_PAD, BUTTON_RIGHT_STICK, BUTTON_RIGHT_TRIGGER, BUTTON_STEAM,
+    BUTTON_VIEW, BUTTON_X, BUTTON_Y, FEATURE_REPORT_MESSAGE_ID_CALIBRATE_TRACKPADS,
+    FEATURE_REPORT_MESSAGE_ID_CLEAR_DIGITAL_MAPPINGS,
+    FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_CALIBRATION,
+    FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_FACTORY_CALIBRATION,
+    FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_RAW_DATA,
+    FEATURE_REPORT_MESSAGE_ID_SET_DEFAULT_DIGITAL_MAPPINGS,
+    FEATURE_REPORT_MESSAGE_ID_SET_DIGITAL_MAPPINGS, FEATURE_REPORT_MESSAGE_ID_TRIGGER_HAPTIC_PULSE,
+    HID_FEATURE_REPORT_BYTES, LEFT_STICK_USED, RIGHT_STICK_USED,
+    VALVE_IN_REPORT_MESSAGE_ID_CONTROLLER_DECK_STATE,
 };
+use response::StickResponse;
+use seqlock::SeqLock;
+use stats::{InputStats, StatsCollector};
+use thread_config::ThreadConfig;
+use trackpad::{TrackpadMouse, TrackpadMouseConfig, TrackpadSample};
+use trackpad_diagnostics::{
+    RawDataStream, TrackpadCalibrationComparison, TrackpadRawChunk, MAX_QUEUED_RAW_CHUNKS,
+};
+use trigger::{Trigger, TriggerStage, TriggerStageTracker, TriggerThresholds};
 
+#[cfg(feature = "async")]
+pub mod async_input;
+pub mod builder;
+pub mod calibration;
+pub mod callback;
+pub mod config;
+pub mod diagnostics;
+pub mod enumerate;
+pub mod fusion;
+pub mod gesture;
+pub mod gyro;
+pub mod mapping;
+pub mod normalization;
+pub mod pad_modes;
+#[cfg(feature = "serde")]
+pub mod profile;
 pub mod protocol;
+pub mod response;
+mod seqlock;
+#[cfg(feature = "serde")]
+mod serde_format;
+pub mod stats;
+pub mod subscription;
+pub mod thread_config;
+pub mod trackpad;
+pub mod trackpad_diagnostics;
+pub mod trigger;
 
 #[derive(Copy, Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GamepadState {
-    pub buttons: [u8; 22],
+    /// Buttons held in the latest packet. Presses and releases between two states are
+    /// counted in `presses` and `releases`, see `press_count`.
+    pub buttons: [u8; 24],
+    /// Running count of presses per button, wrapping.
+    pub presses: [u32; 24],
+    pub releases: [u32; 24],
+    /// The packet's button mask after `InputSettings::button_mapping`, including outputs
+    /// beyond the 24 `buttons`.
+    pub mapped_buttons: u64,
+    /// Presses and releases of `mapped_buttons`, see `virtual_press_count`.
+    pub mapped_counts: MaskCounts,
     pub axes: [f32; 6],
+    pub orientation: Quaternion,
+    pub gyro_stick: [f32; 2],
+    /// Relative pointer motion since the consumer's previous fetch. In `drain_since`, packet
+    /// callbacks and streams of every packet it is the motion of that packet alone.
+    pub mouse_delta: [f32; 2],
+    /// Running sum of all pointer motion, so each consumer can take its own deltas.
+    pub mouse_total: [f64; 2],
+    /// Virtual buttons of the trackpad modes held in the latest packet, see
+    /// `pad_modes::TrackpadMode`.
+    pub left_pad_buttons: u64,
+    pub right_pad_buttons: u64,
+    pub left_pad_counts: MaskCounts,
+    pub right_pad_counts: MaskCounts,
+    pub left_trigger_stage: TriggerStage,
+    pub right_trigger_stage: TriggerStage,
+    /// Thumb resting on the capacitive stick cap.
+    pub left_stick_touched: bool,
+    pub right_stick_touched: bool,
+    /// Trackpad positions, -1..1 on both axes.
+    pub left_pad: [f32; 2],
+    pub right_pad: [f32; 2],
+    pub left_pad_touched: bool,
+    pub right_pad_touched: bool,
+    pub packet: PacketInfo,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl GamepadState {
+    /// How often `buttons[button]` was pressed after `previous` up to this state.
+    pub fn press_count(&self, previous: &GamepadState, button: usize) -> u32 {
+        self.presses[button].wrapping_sub(previous.presses[button])
+    }
+
+    pub fn release_count(&self, previous: &GamepadState, button: usize) -> u32 {
+        self.releases[button].wrapping_sub(previous.releases[button])
+    }
+
+    /// Pressed at least once after `previous`, even if already released again.
+    pub fn just_pressed(&self, previous: &GamepadState, button: usize) -> bool {
+        self.press_count(previous, button) > 0
+    }
+
+    pub fn just_released(&self, previous: &GamepadState, button: usize) -> bool {
+        self.release_count(previous, button) > 0
+    }
+
+    pub fn pad_buttons(&self, pad: Trackpad) -> u64 {
+        match pad {
+            Trackpad::Left => self.left_pad_buttons,
+            Trackpad::Right => self.right_pad_buttons,
+        }
+    }
+
+    pub fn virtual_buttons(&self, buttons: VirtualButtons) -> u64 {
+        match buttons {
+            VirtualButtons::LeftPad => self.left_pad_buttons,
+            VirtualButtons::RightPad => self.right_pad_buttons,
+            VirtualButtons::Mapped => self.mapped_buttons,
+        }
+    }
+
+    fn virtual_counts(&self, buttons: VirtualButtons) -> &MaskCounts {
+        match buttons {
+            VirtualButtons::LeftPad => &self.left_pad_counts,
+            VirtualButtons::RightPad => &self.right_pad_counts,
+            VirtualButtons::Mapped => &self.mapped_counts,
+        }
+    }
+
+    /// How often `bit` of `virtual_buttons(buttons)` was pressed after `previous` up to this
+    /// state, like `press_count` for `buttons`.
+    pub fn virtual_press_count(
+        &self,
+        previous: &GamepadState,
+        buttons: VirtualButtons,
+        bit: usize,
+    ) -> u32 {
+        let presses = previous.virtual_counts(buttons).presses(bit);
+        self.virtual_counts(buttons)
+            .presses(bit)
+            .wrapping_sub(presses)
+    }
+
+    pub fn virtual_release_count(
+        &self,
+        previous: &GamepadState,
+        buttons: VirtualButtons,
+        bit: usize,
+    ) -> u32 {
+        let releases = previous.virtual_counts(buttons).releases(bit);
+        self.virtual_counts(buttons)
+            .releases(bit)
+            .wrapping_sub(releases)
+    }
+
+    pub fn virtual_just_pressed(
+        &self,
+        previous: &GamepadState,
+        buttons: VirtualButtons,
+        bit: usize,
+    ) -> bool {
+        self.virtual_press_count(previous, buttons, bit) > 0
+    }
+
+    pub fn virtual_just_released(
+        &self,
+        previous: &GamepadState,
+        buttons: VirtualButtons,
+        bit: usize,
+    ) -> bool {
+        self.virtual_release_count(previous, buttons, bit) > 0
+    }
+}
+
+/// Button masks produced on the host rather than read from the device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VirtualButtons {
+    LeftPad,
+    RightPad,
+    Mapped,
+}
+
+/// Running press and release counts for each bit of a button mask, wrapping, so a tap that
+/// starts and ends between two fetches is still seen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(into = "MaskCountsRepr", try_from = "MaskCountsRepr")
+)]
+pub struct MaskCounts {
+    presses: [u32; 64],
+    releases: [u32; 64],
+}
+
+impl Default for MaskCounts {
+    fn default() -> Self {
+        MaskCounts {
+            presses: [0; 64],
+            releases: [0; 64],
+        }
+    }
+}
+
+impl MaskCounts {
+    pub fn presses(&self, bit: usize) -> u32 {
+        self.presses[bit]
+    }
+
+    pub fn releases(&self, bit: usize) -> u32 {
+        self.releases[bit]
+    }
+
+    fn update(&mut self, previous: u64, current: u64) {
+        let changed = previous ^ current;
+        for bit in 0..64 {
+            if changed & (1 << bit) == 0 {
+                continue;
+            }
+            let counts = if current & (1 << bit) != 0 {
+                &mut self.presses
+            } else {
+                &mut self.releases
+            };
+            counts[bit] = counts[bit].wrapping_add(1);
+        }
+    }
+}
+
+/// serde only derives for arrays up to 32 elements.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MaskCountsRepr {
+    presses: Vec<u32>,
+    releases: Vec<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl From<MaskCounts> for MaskCountsRepr {
+    fn from(counts: MaskCounts) -> Self {
+        MaskCountsRepr {
+            presses: counts.presses.to_vec(),
+            releases: counts.releases.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<MaskCountsRepr> for MaskCounts {
+    type Error = String;
+
+    fn try_from(repr: MaskCountsRepr) -> Result<Self, Self::Error> {
+        let counts = |counts: Vec<u32>| {
+            <[u32; 64]>::try_from(counts).map_err(|_| "expected 64 counts".to_string())
+        };
+        Ok(MaskCounts {
+            presses: counts(repr.presses)?,
+            releases: counts(repr.releases)?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Trackpad {
+    Left,
+    Right,
+}
+
+impl Trackpad {
+    pub(crate) fn pad_num(self) -> u8 {
+        match self {
+            Trackpad::Right => 0,
+            Trackpad::Left => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketInfo {
+    /// The device's `packet_num` of the latest packet.
+    pub packet_num: u32,
+    /// Host time the latest packet was read, `None` until one arrives.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub received: Option<Instant>,
+    /// Time between the two latest packets.
+    pub interval: Duration,
+    /// Gaps in `packet_num` since the device connected.
+    pub dropped: u64,
+    /// Repeated `packet_num`s since the device connected.
+    pub duplicated: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InputEventKind {
+    RadialMenuSelected {
+        pad: Trackpad,
+        slice: usize,
+    },
+    Gesture {
+        pad: Trackpad,
+        gesture: Gesture,
+    },
+    TriggerStage {
+        trigger: Trigger,
+        stage: TriggerStage,
+        previous: TriggerStage,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InputEvent {
+    /// Receive time of the packet that produced the event.
+    pub time: Instant,
+    pub kind: InputEventKind,
+}
+
+#[derive(Clone, Debug)]
 pub struct GamepadUpdateState {
     pub gamepad: GamepadState,
     pub last_update_time: Instant,
-    pub fetched: bool,
+    /// The settings the processors below were built from.
+    pub settings: InputSettings,
+    pub settings_generation: u64,
+    pub fusion: OrientationFilter,
+    pub gyro_aim: GyroAim,
+    pub trackpad_mouse: Option<TrackpadMouse>,
+    pub left_pad_mode: Option<TrackpadModeState>,
+    pub right_pad_mode: Option<TrackpadModeState>,
+    pub left_gestures: Option<GestureRecognizer>,
+    pub right_gestures: Option<GestureRecognizer>,
+    pub left_trigger: TriggerStageTracker,
+    pub right_trigger: TriggerStageTracker,
+    pub button_mapper: ButtonMapper,
+}
+
+/// Everything consumers can configure about how packets become a `GamepadState`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct InputSettings {
+    pub calibration: DeviceCalibration,
+    pub fusion_filter: FusionFilter,
+    pub gyro_aim: GyroAimConfig,
+    pub trackpad_mouse: Option<TrackpadMouseConfig>,
+    pub left_pad_mode: Option<TrackpadMode>,
+    pub right_pad_mode: Option<TrackpadMode>,
+    pub left_gestures: Option<GestureConfig>,
+    pub right_gestures: Option<GestureConfig>,
+    pub trigger_thresholds: TriggerThresholds,
+    pub left_stick_response: StickResponse,
+    pub right_stick_response: StickResponse,
+    /// Report centered sticks unless a thumb is on the cap.
+    pub left_stick_requires_touch: bool,
+    pub right_stick_requires_touch: bool,
+    pub normalization: Normalization,
+    pub lizard_mode: LizardMode,
+    pub button_mapping: ButtonMapping,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FetchedState {
+    pub state: GamepadState,
+    /// Time since the packet `state` was decoded from arrived.
+    pub age: Duration,
+    /// No packet within the stale timeout, or the device is gone.
+    pub stale: bool,
+    pub connected: bool,
 }
 
 impl GamepadUpdateState {
-    fn update(&mut self, new: &SteamDeckStatePacket) {
-        self.gamepad.axes[0] = (new.left_stick_x as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
-        self.gamepad.axes[1] = -(new.left_stick_y as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
-        self.gamepad.axes[2] = (new.right_stick_x as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
-        self.gamepad.axes[3] = -(new.right_stick_y as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
-        self.gamepad.axes[4] =
-            (new.trigger_raw_l as f32 / i16::MAX as f32).clamp(0.0, 1.0) * 2.0 - 1.0;
-        self.gamepad.axes[5] =
-            (new.trigger_raw_r as f32 / i16::MAX as f32).clamp(0.0, 1.0) * 2.0 - 1.0;
-
-        let b = &mut self.gamepad.buttons;
-
-        b[0] = (((new.buttons & BUTTON_A) > 0) || (b[0] != 0 && !self.fetched)) as u8;
-        b[1] = (((new.buttons & BUTTON_B) > 0) || (b[1] != 0 && !self.fetched)) as u8;
-        b[2] = (((new.buttons & BUTTON_X) > 0) || (b[2] != 0 && !self.fetched)) as u8;
-        b[3] = (((new.buttons & BUTTON_Y) > 0) || (b[3] != 0 && !self.fetched)) as u8;
-        b[4] = (((new.buttons & BUTTON_LEFT_BUMPER) > 0) || (b[4] != 0 && !self.fetched)) as u8;
-        b[5] = (((new.buttons & BUTTON_RIGHT_BUMPER) > 0) || (b[5] != 0 && !self.fetched)) as u8;
-        b[6] = (((new.buttons & BUTTON_VIEW) > 0) || (b[6] != 0 && !self.fetched)) as u8;
-        b[7] = (((new.buttons & BUTTON_MENU) > 0) || (b[7] != 0 && !self.fetched)) as u8;
-        b[8] = (((new.buttons & BUTTON_QUICK_ACCESS) > 0) || (b[8] != 0 && !self.fetched)) as u8;
-        b[9] = (((new.buttons & BUTTON_LEFT_STICK) > 0) || (b[9] != 0 && !self.fetched)) as u8;
-        b[10] = (((new.buttons & BUTTON_RIGHT_STICK) > 0) || (b[10] != 0 && !self.fetched)) as u8;
-        b[11] = (((new.buttons & BUTTON_DPAD_UP) > 0) || (b[11] != 0 && !self.fetched)) as u8;
-        b[12] = (((new.buttons & BUTTON_DPAD_RIGHT) > 0) || (b[12] != 0 && !self.fetched)) as u8;
-        b[13] = (((new.buttons & BUTTON_DPAD_DOWN) > 0) || (b[13] != 0 && !self.fetched)) as u8;
-        b[14] = (((new.buttons & BUTTON_DPAD_LEFT) > 0) || (b[14] != 0 && !self.fetched)) as u8;
-        b[15] = (((new.buttons & BUTTON_R4) > 0) || (b[15] != 0 && !self.fetched)) as u8;
-        b[16] = (((new.buttons & BUTTON_R5) > 0) || (b[16] != 0 && !self.fetched)) as u8;
-        b[17] = (((new.buttons & BUTTON_L4) > 0) || (b[17] != 0 && !self.fetched)) as u8;
-        b[18] = (((new.buttons & BUTTON_L5) > 0) || (b[18] != 0 && !self.fetched)) as u8;
-        b[19] = (((new.buttons & BUTTON_STEAM) > 0) || (b[19] != 0 && !self.fetched)) as u8;
-        b[20] = (((new.buttons & BUTTON_LEFT_PAD) > 0) || (b[20] != 0 && !self.fetched)) as u8;
-        b[21] = (((new.buttons & BUTTON_RIGHT_PAD) > 0) || (b[21] != 0 && !self.fetched)) as u8;
-
-        self.last_update_time = Instant::now();
-        self.fetched = false;
-    }
-
-    fn fetch(&mut self) -> Option<GamepadState> {
-        self.fetched = true;
-        if self.last_update_time.elapsed() < Duration::from_millis(100) {
-            Some(self.gamepad)
-        } else {
-            None
+    fn new(settings: InputSettings, settings_generation: u64) -> GamepadUpdateState {
+        GamepadUpdateState {
+            gamepad: Default::default(),
+            last_update_time: Instant::now(),
+            settings_generation,
+            fusion: OrientationFilter::new(settings.fusion_filter),
+            gyro_aim: GyroAim::new(settings.gyro_aim),
+            trackpad_mouse: settings.trackpad_mouse.map(TrackpadMouse::new),
+            left_pad_mode: settings
+                .left_pad_mode
+                .map(|mode| TrackpadModeState::new(Trackpad::Left, mode)),
+            right_pad_mode: settings
+                .right_pad_mode
+                .map(|mode| TrackpadModeState::new(Trackpad::Right, mode)),
+            left_gestures: settings.left_gestures.map(GestureRecognizer::new),
+            right_gestures: settings.right_gestures.map(GestureRecognizer::new),
+            left_trigger: TriggerStageTracker::new(settings.trigger_thresholds),
+            right_trigger: TriggerStageTracker::new(settings.trigger_thresholds),
+            button_mapper: ButtonMapper::new(settings.button_mapping.clone()),
+            settings,
+        }
+    }
+
+    /// Picks up settings changed by consumers. Never waits on them; if the settings are
+    /// being written right now they are applied with the next packet instead, and this
+    /// returns false.
+    fn sync_settings(&mut self, shared: &SteamdeckShared) -> bool {
+        let generation = shared.settings_generation.load(Ordering::Acquire);
+        if generation == self.settings_generation {
+            return true;
+        }
+        let Ok(settings) = shared.settings.try_lock() else {
+            return false;
+        };
+        let settings = settings.clone();
+        self.settings_generation = generation;
+
+        // Only rebuild what changed, so e.g. a new calibration doesn't reset gesture state
+        let old = mem::replace(&mut self.settings, settings);
+        let settings = &self.settings;
+        if settings.fusion_filter != old.fusion_filter {
+            self.fusion.set_filter(settings.fusion_filter);
+        }
+        if settings.gyro_aim != old.gyro_aim {
+            self.gyro_aim.set_config(settings.gyro_aim);
+        }
+        if settings.trackpad_mouse != old.trackpad_mouse {
+            self.trackpad_mouse = settings.trackpad_mouse.map(TrackpadMouse::new);
+        }
+        if settings.left_pad_mode != old.left_pad_mode {
+            self.left_pad_mode = settings
+                .left_pad_mode
+                .map(|mode| TrackpadModeState::new(Trackpad::Left, mode));
+        }
+        if settings.right_pad_mode != old.right_pad_mode {
+            self.right_pad_mode = settings
+                .right_pad_mode
+                .map(|mode| TrackpadModeState::new(Trackpad::Right, mode));
+        }
+        if settings.left_gestures != old.left_gestures {
+            self.left_gestures = settings.left_gestures.map(GestureRecognizer::new);
+        }
+        if settings.right_gestures != old.right_gestures {
+            self.right_gestures = settings.right_gestures.map(GestureRecognizer::new);
+        }
+        if settings.trigger_thresholds != old.trigger_thresholds {
+            self.left_trigger = TriggerStageTracker::new(settings.trigger_thresholds);
+            self.right_trigger = TriggerStageTracker::new(settings.trigger_thresholds);
+        }
+        if settings.button_mapping != old.button_mapping {
+            self.button_mapper = ButtonMapper::new(settings.button_mapping.clone());
+        }
+        true
+    }
+
+    /// Returns the trackpad that should play a haptic tick, if any.
+    fn update(
+        &mut self,
+        new: &SteamDeckStatePacket,
+        now: Instant,
+        events: &mut Vec<InputEvent>,
+    ) -> Option<Trackpad> {
+        self.update_packet_info(new.packet_num, now);
+
+        // Don't integrate over gaps, e.g. the first packet after a reconnect
+        let dt = now.duration_since(self.last_update_time).as_secs_f32();
+        let dt = if dt < MAX_PACKET_DT { dt } else { 0.0 };
+
+        let mut mouse_delta = [0.0; 2];
+
+        let imu = ImuSample::from_packet(new);
+        if dt > 0.0 {
+            self.fusion.update(&imu, dt);
+        }
+        self.gamepad.orientation = self.fusion.orientation();
+
+        let gyro = self.gyro_aim.update(new.buttons, &imu, dt);
+        self.gamepad.gyro_stick = [gyro.stick[0], self.settings.normalization.y(gyro.stick[1])];
+        mouse_delta[0] += gyro.mouse[0];
+        mouse_delta[1] += gyro.mouse[1];
+
+        let mut haptic_tick = None;
+        if let Some(mouse) = &mut self.trackpad_mouse {
+            let pad = mouse.config().pad;
+            let output = mouse.update(&TrackpadSample::from_packet(new, pad), dt);
+            mouse_delta[0] += output.delta[0];
+            mouse_delta[1] += output.delta[1];
+            if output.haptic_tick {
+                haptic_tick = Some(pad);
+            }
+        }
+
+        self.gamepad.mouse_delta = mouse_delta;
+        self.gamepad.mouse_total[0] += mouse_delta[0] as f64;
+        self.gamepad.mouse_total[1] += mouse_delta[1] as f64;
+
+        let cal = &self.settings.calibration;
+        let norm = &self.settings.normalization;
+        let left = self.settings.left_stick_response.apply([
+            cal.left_stick_x.normalize(new.left_stick_x),
+            -cal.left_stick_y.normalize(new.left_stick_y),
+        ]);
+        let right = self.settings.right_stick_response.apply([
+            cal.right_stick_x.normalize(new.right_stick_x),
+            -cal.right_stick_y.normalize(new.right_stick_y),
+        ]);
+        self.gamepad.axes[0] = left[0];
+        self.gamepad.axes[1] = norm.y(left[1]);
+        self.gamepad.axes[2] = right[0];
+        self.gamepad.axes[3] = norm.y(right[1]);
+
+        self.gamepad.left_stick_touched = new.buttons & LEFT_STICK_USED != 0;
+        self.gamepad.right_stick_touched = new.buttons & RIGHT_STICK_USED != 0;
+        if self.settings.left_stick_requires_touch && !self.gamepad.left_stick_touched {
+            self.gamepad.axes[0] = 0.0;
+            self.gamepad.axes[1] = 0.0;
+        }
+        if self.settings.right_stick_requires_touch && !self.gamepad.right_stick_touched {
+            self.gamepad.axes[2] = 0.0;
+            self.gamepad.axes[3] = 0.0;
+        }
+        let trigger_l = cal.left_trigger.normalize(new.trigger_raw_l);
+        let trigger_r = cal.right_trigger.normalize(new.trigger_raw_r);
+        self.gamepad.axes[4] = norm.trigger_range.apply(trigger_l, new.trigger_raw_l);
+        self.gamepad.axes[5] = norm.trigger_range.apply(trigger_r, new.trigger_raw_r);
+
+        for (pad, position, touched) in [
+            (
+                Trackpad::Left,
+                &mut self.gamepad.left_pad,
+                &mut self.gamepad.left_pad_touched,
+            ),
+            (
+                Trackpad::Right,
+                &mut self.gamepad.right_pad,
+                &mut self.gamepad.right_pad_touched,
+            ),
+        ] {
+            let sample = TrackpadSample::from_packet(new, pad);
+            *position = [sample.position[0], norm.y(sample.position[1])];
+            *touched = sample.touched;
+        }
+
+        let mut event_kinds = Vec::new();
+
+        for (tracker, trigger, value, digital, stage) in [
+            (
+                &mut self.left_trigger,
+                Trigger::Left,
+                trigger_l,
+                BUTTON_LEFT_TRIGGER,
+                &mut self.gamepad.left_trigger_stage,
+            ),
+            (
+                &mut self.right_trigger,
+                Trigger::Right,
+                trigger_r,
+                BUTTON_RIGHT_TRIGGER,
+                &mut self.gamepad.right_trigger_stage,
+            ),
+        ] {
+            if let Some(previous) = tracker.update(value, new.buttons & digital != 0) {
+                event_kinds.push(InputEventKind::TriggerStage {
+                    trigger,
+                    stage: tracker.stage(),
+                    previous,
+                });
+            }
+            *stage = tracker.stage();
+        }
+
+        for (mode, click, pad_buttons, counts) in [
+            (
+                &mut self.left_pad_mode,
+                BUTTON_LEFT_PAD,
+                &mut self.gamepad.left_pad_buttons,
+                &mut self.gamepad.left_pad_counts,
+            ),
+            (
+                &mut self.right_pad_mode,
+                BUTTON_RIGHT_PAD,
+                &mut self.gamepad.right_pad_buttons,
+                &mut self.gamepad.right_pad_counts,
+            ),
+        ] {
+            let buttons = match mode {
+                Some(mode) => {
+                    let sample = TrackpadSample::from_packet(new, mode.pad());
+                    mode.update(&sample, new.buttons & click != 0, &mut event_kinds)
+                }
+                None => 0,
+            };
+            counts.update(*pad_buttons, buttons);
+            *pad_buttons = buttons;
+        }
+
+        for (recognizer, pad) in [
+            (&mut self.left_gestures, Trackpad::Left),
+            (&mut self.right_gestures, Trackpad::Right),
+        ] {
+            if let Some(recognizer) = recognizer {
+                let mut gestures = Vec::new();
+                recognizer.update(&TrackpadSample::from_packet(new, pad), now, &mut gestures);
+                event_kinds.extend(
+                    gestures
+                        .into_iter()
+                        .map(|gesture| InputEventKind::Gesture { pad, gesture }),
+                );
+            }
+        }
+
+        events.extend(
+            event_kinds
+                .into_iter()
+                .map(|kind| InputEvent { time: now, kind }),
+        );
+
+        let buttons = self.button_mapper.update(new.buttons, now);
+        self.gamepad
+            .mapped_counts
+            .update(self.gamepad.mapped_buttons, buttons);
+        self.gamepad.mapped_buttons = buttons;
+        let pressed = BUTTON_MAPPING.map(|button| (buttons & button != 0) as u8);
+        for (i, pressed) in pressed.into_iter().enumerate() {
+            match (self.gamepad.buttons[i], pressed) {
+                (0, 1) => self.gamepad.presses[i] = self.gamepad.presses[i].wrapping_add(1),
+                (1, 0) => self.gamepad.releases[i] = self.gamepad.releases[i].wrapping_add(1),
+                _ => {}
+            }
+        }
+        self.gamepad.buttons = pressed;
+
+        self.last_update_time = now;
+
+        haptic_tick
+    }
+
+    fn update_packet_info(&mut self, packet_num: u32, now: Instant) {
+        let info = &mut self.gamepad.packet;
+
+        if let Some(received) = info.received {
+            info.interval = now.duration_since(received);
+
+            // A jump backwards means the counter restarted, not a loss
+            match packet_num.wrapping_sub(info.packet_num) {
+                0 => info.duplicated += 1,
+                gap if gap <= i32::MAX as u32 => info.dropped += gap as u64 - 1,
+                _ => {}
+            }
         }
+
+        info.packet_num = packet_num;
+        info.received = Some(now);
     }
 }
 
+/// Bit of each `GamepadState::buttons` entry in the packet's button mask.
+const BUTTON_MAPPING: [u64; 24] = [
+    BUTTON_A,
+    BUTTON_B,
+    BUTTON_X,
+    BUTTON_Y,
+    BUTTON_LEFT_BUMPER,
+    BUTTON_RIGHT_BUMPER,
+    BUTTON_VIEW,
+    BUTTON_MENU,
+    BUTTON_QUICK_ACCESS,
+    BUTTON_LEFT_STICK,
+    BUTTON_RIGHT_STICK,
+    BUTTON_DPAD_UP,
+    BUTTON_DPAD_RIGHT,
+    BUTTON_DPAD_DOWN,
+    BUTTON_DPAD_LEFT,
+    BUTTON_R4,
+    BUTTON_R5,
+    BUTTON_L4,
+    BUTTON_L5,
+    BUTTON_STEAM,
+    BUTTON_LEFT_PAD,
+    BUTTON_RIGHT_PAD,
+    BUTTON_LEFT_TRIGGER,
+    BUTTON_RIGHT_TRIGGER,
+];
+
+const MAX_PACKET_DT: f32 = 0.1;
+const HAPTIC_TICK_DURATION_US: u16 = 200;
+const MAX_QUEUED_EVENTS: usize = 256;
+/// About four seconds of packets.
+const HISTORY_CAPACITY: usize = 1024;
+const STATS_PUBLISH_PACKETS: u64 = 25;
+
+type FeatureReportBuf = [u8; HID_FEATURE_REPORT_BYTES + 1];
+
+struct DeviceRequest {
+    report: FeatureReportBuf,
+    read_reply: bool,
+    /// `REQUEST_*`; whoever moves it away from `REQUEST_PENDING` first decides whether the
+    /// report is sent.
+    status: Arc<AtomicU8>,
+    reply: mpsc::Sender<Result<FeatureReportBuf, SteamDeckInputError>>,
+}
+
+const REQUEST_PENDING: u8 = 0;
+const REQUEST_STARTED: u8 = 1;
+const REQUEST_CANCELLED: u8 = 2;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
 struct SteamdeckShared {
+    config: SteamdeckConfig,
     run: AtomicBool,
     found: AtomicBool,
-    state: Mutex<GamepadUpdateState>,
+    /// Written only by the input thread, after every packet.
+    gamepad: SeqLock<GamepadState>,
+    stale_timeout_us: AtomicU64,
+    settings: Mutex<InputSettings>,
+    settings_generation: AtomicU64,
+    reset_heading: AtomicBool,
+    calibrating: AtomicBool,
+    calibrator: Mutex<Option<Calibrator>>,
+    serial: Mutex<Option<String>>,
+    calibrations: Mutex<HashMap<String, DeviceCalibration>>,
+    /// Set while no device with a serial number is connected, saved for the next one.
+    pending_calibration: Mutex<Option<DeviceCalibration>>,
+    requests: Mutex<Vec<DeviceRequest>>,
+    raw_stream: Mutex<Option<Trackpad>>,
+    raw_chunks: Mutex<VecDeque<TrackpadRawChunk>>,
+    events: Mutex<VecDeque<InputEvent>>,
+    history: Mutex<VecDeque<GamepadState>>,
+    #[cfg(feature = "async")]
+    wakers: Mutex<Vec<std::task::Waker>>,
+    stats: Mutex<InputStats>,
+    reset_stats: AtomicBool,
+    stats_log_interval_ms: AtomicU64,
+    fetches: AtomicU64,
+    fetch_age_total_us: AtomicU64,
+    fetch_age_max_us: AtomicU64,
+    callback_panics: AtomicU64,
+    callback_drops: AtomicU64,
+}
+
+impl SteamdeckShared {
+    fn update_settings(&self, f: impl FnOnce(&mut InputSettings)) {
+        f(&mut self.settings.lock().unwrap());
+        self.settings_generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn stats(&self) -> InputStats {
+        let mut stats = *self.stats.lock().unwrap();
+        stats.fetches = self.fetches.load(Ordering::Relaxed);
+        if let Some(mean) = self
+            .fetch_age_total_us
+            .load(Ordering::Relaxed)
+            .checked_div(stats.fetches)
+        {
+            stats.fetch_age_mean = Duration::from_micros(mean);
+        }
+        stats.fetch_age_max = Duration::from_micros(self.fetch_age_max_us.load(Ordering::Relaxed));
+        stats.callback_panics = self.callback_panics.load(Ordering::Relaxed);
+        stats.callback_drops = self.callback_drops.load(Ordering::Relaxed);
+        stats
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn wake_consumers(&self) {}
+
+    /// Remembers `calibration` for the connected device, or for the next one to connect.
+    fn remember_calibration(&self, calibration: DeviceCalibration) {
+        let serial = self
+            .serial
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|_| self.found.load(Ordering::SeqCst));
+        match serial {
+            Some(serial) => {
+                self.calibrations
+                    .lock()
+                    .unwrap()
+                    .insert(serial, calibration);
+            }
+            None => *self.pending_calibration.lock().unwrap() = Some(calibration),
+        }
+    }
+
+    /// The published state, without any of `fetch`'s bookkeeping.
+    fn snapshot(&self, stale_timeout: Duration) -> Option<FetchedState> {
+        let connected = self.found.load(Ordering::SeqCst);
+        let state = self.gamepad.read();
+        let age = state.packet.received?.elapsed();
+        Some(FetchedState {
+            state,
+            age,
+            stale: !connected || age >= stale_timeout,
+            connected,
+        })
+    }
+
+    fn record_fetch_age(&self, age: Duration) {
+        let age_us = age.as_micros() as u64;
+        self.fetches.fetch_add(1, Ordering::Relaxed);
+        self.fetch_age_total_us.fetch_add(age_us, Ordering::Relaxed);
+        self.fetch_age_max_us.fetch_max(age_us, Ordering::Relaxed);
+    }
 }
 
 pub struct SteamdeckInput {
     shared: Arc<SteamdeckShared>,
+    /// `GamepadState::mouse_total` at the previous `fetch`.
+    fetched_mouse_total: Mutex<[f64; 2]>,
     thread: Option<JoinHandle<()>>,
+    callback_thread: Option<JoinHandle<()>>,
 }
 
 impl SteamdeckInput {
+    /// Panics if the input thread can't be spawned; `builder` returns that error instead.
     pub fn new() -> SteamdeckInput {
+        Self::builder()
+            .build()
+            .expect("failed to spawn the input thread")
+    }
+
+    /// For configuring the input thread and a packet callback.
+    pub fn builder() -> SteamdeckInputBuilder {
+        SteamdeckInputBuilder::default()
+    }
+
+    /// Calls `callback` for every packet right after it is decoded, on the input thread.
+    /// Panics in the callback are caught, see `InputStats::callback_panics`. Like `new`, panics
+    /// if a thread can't be spawned.
+    pub fn with_callback(callback: impl FnMut(&DeckSample) + Send + 'static) -> SteamdeckInput {
+        Self::with_callback_on(CallbackThread::Reader, callback)
+    }
+
+    pub fn with_callback_on(
+        thread: CallbackThread,
+        callback: impl FnMut(&DeckSample) + Send + 'static,
+    ) -> SteamdeckInput {
+        Self::builder()
+            .callback(thread, callback)
+            .build()
+            .expect("failed to spawn the input or callback thread")
+    }
+
+    pub(crate) fn spawn(
+        config: SteamdeckConfig,
+        thread: &ThreadConfig,
+        callback: Option<(Callback, CallbackThread)>,
+    ) -> Result<SteamdeckInput, SteamDeckInputError> {
+        config.validate()?;
+
+        let settings = InputSettings {
+            normalization: config.normalization,
+            lizard_mode: config.lizard_mode,
+            ..Default::default()
+        };
+        let stale_timeout_us = config.stale_timeout.as_micros() as u64;
         let shared = Arc::new(SteamdeckShared {
+            config,
             found: AtomicBool::new(false),
             run: AtomicBool::new(true),
-            state: Mutex::new(GamepadUpdateState {
-                gamepad: Default::default(),
-                last_update_time: Instant::now(),
-                fetched: false,
-            }),
+            gamepad: SeqLock::new(GamepadState::default()),
+            stale_timeout_us: AtomicU64::new(stale_timeout_us),
+            settings: Mutex::new(settings),
+            settings_generation: AtomicU64::new(0),
+            reset_heading: AtomicBool::new(false),
+            calibrating: AtomicBool::new(false),
+            calibrator: Mutex::new(None),
+            serial: Mutex::new(None),
+            calibrations: Mutex::new(HashMap::new()),
+            pending_calibration: Mutex::new(None),
+            requests: Mutex::new(Vec::new()),
+            raw_stream: Mutex::new(None),
+            raw_chunks: Mutex::new(VecDeque::new()),
+            events: Mutex::new(VecDeque::new()),
+            history: Mutex::new(VecDeque::with_capacity(HISTORY_CAPACITY)),
+            #[cfg(feature = "async")]
+            wakers: Mutex::new(Vec::new()),
+            stats: Mutex::new(InputStats::default()),
+            reset_stats: AtomicBool::new(false),
+            stats_log_interval_ms: AtomicU64::new(0),
+            fetches: AtomicU64::new(0),
+            fetch_age_total_us: AtomicU64::new(0),
+            fetch_age_max_us: AtomicU64::new(0),
+            callback_panics: AtomicU64::new(0),
+            callback_drops: AtomicU64::new(0),
         });
 
-        let thread = Some(thread::spawn({
+        let (callback, callback_thread) = match callback {
+            Some((callback, thread)) => {
+                let (dispatch, handle) = CallbackDispatch::new(callback, thread, &shared)?;
+                (Some(dispatch), handle)
+            }
+            None => (None, None),
+        };
+
+        let thread = Some(thread.spawn("steamdeck-input", {
             let shared = shared.clone();
             move || {
-                steamdeck_input_thread(shared);
+                steamdeck_input_thread(shared, callback);
             }
-        }));
+        })?);
 
-        SteamdeckInput { shared, thread }
+        Ok(SteamdeckInput {
+            shared,
+            fetched_mouse_total: Mutex::new([0.0; 2]),
+            thread,
+            callback_thread,
+        })
     }
 
+    /// The latest state, or `None` if it is stale or no device is connected.
     pub fn fetch(&self) -> Option<GamepadState> {
-        if self.shared.found.load(Ordering::SeqCst) {
-            self.shared.state.lock().unwrap().fetch()
-        } else {
-            None
+        self.fetch_with_age()
+            .filter(|fetched| !fetched.stale)
+            .map(|fetched| fetched.state)
+    }
+
+    /// The latest state even if it is stale, so callers can tell a quiet device from a lost
+    /// one. After a disconnect the last state stays available as stale until the device is
+    /// found again; from then until its first packet this returns `None`.
+    pub fn fetch_with_age(&self) -> Option<FetchedState> {
+        let mut fetched = self.shared.snapshot(self.stale_timeout())?;
+        let state = &mut fetched.state;
+
+        let previous = mem::replace(
+            &mut *self.fetched_mouse_total.lock().unwrap(),
+            state.mouse_total,
+        );
+        state.mouse_delta = [
+            (state.mouse_total[0] - previous[0]) as f32,
+            (state.mouse_total[1] - previous[1]) as f32,
+        ];
+
+        if fetched.connected {
+            self.shared.record_fetch_age(fetched.age);
+        }
+        Some(fetched)
+    }
+
+    pub fn stale_timeout(&self) -> Duration {
+        Duration::from_micros(self.shared.stale_timeout_us.load(Ordering::Relaxed))
+    }
+
+    pub fn set_stale_timeout(&self, timeout: Duration) {
+        self.shared
+            .stale_timeout_us
+            .store(timeout.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn settings(&self) -> InputSettings {
+        self.shared.settings.lock().unwrap().clone()
+    }
+
+    /// Replaces all settings at once; the input thread applies them with the next packet.
+    pub fn set_settings(&self, settings: InputSettings) {
+        if settings.calibration != self.calibration() {
+            self.shared.remember_calibration(settings.calibration);
+        }
+        self.shared.update_settings(|s| *s = settings);
+    }
+
+    pub fn stats(&self) -> InputStats {
+        self.shared.stats()
+    }
+
+    pub fn reset_stats(&self) {
+        self.shared.reset_stats.store(true, Ordering::SeqCst);
+        self.shared.fetches.store(0, Ordering::Relaxed);
+        self.shared.fetch_age_total_us.store(0, Ordering::Relaxed);
+        self.shared.fetch_age_max_us.store(0, Ordering::Relaxed);
+        self.shared.callback_panics.store(0, Ordering::Relaxed);
+        self.shared.callback_drops.store(0, Ordering::Relaxed);
+    }
+
+    /// Logs `stats` at info level every `interval` from the input thread, or never with `None`.
+    pub fn set_stats_log_interval(&self, interval: Option<Duration>) {
+        let ms = interval.map(|i| (i.as_millis() as u64).max(1)).unwrap_or(0);
+        self.shared
+            .stats_log_interval_ms
+            .store(ms, Ordering::SeqCst);
+    }
+
+    /// Takes the events produced since the previous call, oldest first.
+    pub fn poll_events(&self) -> Vec<InputEvent> {
+        self.shared.events.lock().unwrap().drain(..).collect()
+    }
+
+    /// Every packet received after `last_packet_num`, oldest first, unlatched and with its own
+    /// `PacketInfo::received` time. Holds the last 1024 packets (about four seconds) of the
+    /// current connection; if `last_packet_num` is not among them (first call, reconnect or overrun)
+    /// all of them are returned. Nothing is removed, so several consumers can keep their own
+    /// `last_packet_num`.
+    pub fn drain_since(&self, last_packet_num: u32) -> Vec<GamepadState> {
+        let history = self.shared.history.lock().unwrap();
+        let start = history
+            .iter()
+            .rposition(|state| state.packet.packet_num == last_packet_num)
+            .map_or(0, |i| i + 1);
+        history.range(start..).copied().collect()
+    }
+
+    pub fn serial_number(&self) -> Option<String> {
+        self.shared.serial.lock().unwrap().clone()
+    }
+
+    pub fn set_fusion_filter(&self, filter: FusionFilter) {
+        self.shared
+            .update_settings(|settings| settings.fusion_filter = filter);
+    }
+
+    /// Makes the direction the Deck currently faces the zero yaw of `GamepadState::orientation`.
+    pub fn reset_heading(&self) {
+        self.shared.reset_heading.store(true, Ordering::Release);
+    }
+
+    pub fn gyro_aim(&self) -> GyroAimConfig {
+        self.settings().gyro_aim
+    }
+
+    pub fn set_gyro_aim(&self, config: GyroAimConfig) {
+        self.shared
+            .update_settings(|settings| settings.gyro_aim = config);
+    }
+
+    pub fn trackpad_mouse(&self) -> Option<TrackpadMouseConfig> {
+        self.settings().trackpad_mouse
+    }
+
+    /// Turns a trackpad into a pointer feeding `GamepadState::mouse_delta`, or disables it with `None`.
+    pub fn set_trackpad_mouse(&self, config: Option<TrackpadMouseConfig>) {
+        self.shared
+            .update_settings(|settings| settings.trackpad_mouse = config);
+    }
+
+    pub fn trackpad_mode(&self, pad: Trackpad) -> Option<TrackpadMode> {
+        let settings = self.settings();
+        match pad {
+            Trackpad::Left => settings.left_pad_mode,
+            Trackpad::Right => settings.right_pad_mode,
+        }
+    }
+
+    pub fn set_trackpad_mode(&self, pad: Trackpad, mode: Option<TrackpadMode>) {
+        self.shared.update_settings(|settings| match pad {
+            Trackpad::Left => settings.left_pad_mode = mode,
+            Trackpad::Right => settings.right_pad_mode = mode,
+        });
+    }
+
+    pub fn gestures(&self, pad: Trackpad) -> Option<GestureConfig> {
+        let settings = self.settings();
+        match pad {
+            Trackpad::Left => settings.left_gestures,
+            Trackpad::Right => settings.right_gestures,
+        }
+    }
+
+    /// Enables gesture events for `pad`, reported through `poll_events`.
+    pub fn set_gestures(&self, pad: Trackpad, config: Option<GestureConfig>) {
+        self.shared.update_settings(|settings| match pad {
+            Trackpad::Left => settings.left_gestures = config,
+            Trackpad::Right => settings.right_gestures = config,
+        });
+    }
+
+    pub fn trigger_thresholds(&self) -> TriggerThresholds {
+        self.settings().trigger_thresholds
+    }
+
+    pub fn set_trigger_thresholds(&self, thresholds: TriggerThresholds) {
+        self.shared
+            .update_settings(|settings| settings.trigger_thresholds = thresholds);
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.settings().normalization
+    }
+
+    pub fn set_normalization(&self, normalization: Normalization) {
+        self.shared
+            .update_settings(|settings| settings.normalization = normalization);
+    }
+
+    pub fn stick_response(&self, stick: Stick) -> StickResponse {
+        let settings = self.settings();
+        match stick {
+            Stick::Left => settings.left_stick_response,
+            Stick::Right => settings.right_stick_response,
+        }
+    }
+
+    /// Fails if `response` doesn't pass `StickResponse::validate`.
+    pub fn set_stick_response(
+        &self,
+        stick: Stick,
+        response: StickResponse,
+    ) -> Result<(), SteamDeckInputError> {
+        response.validate()?;
+        self.shared.update_settings(|settings| match stick {
+            Stick::Left => settings.left_stick_response = response,
+            Stick::Right => settings.right_stick_response = response,
+        });
+        Ok(())
+    }
+
+    pub fn lizard_mode(&self) -> LizardMode {
+        self.settings().lizard_mode
+    }
+
+    /// Switching to `LizardMode::Keep` restores the default mappings right away.
+    pub fn set_lizard_mode(&self, mode: LizardMode) {
+        self.shared
+            .update_settings(|settings| settings.lizard_mode = mode);
+    }
+
+    pub fn button_mapping(&self) -> ButtonMapping {
+        self.settings().button_mapping
+    }
+
+    pub fn set_button_mapping(&self, mapping: ButtonMapping) {
+        self.shared
+            .update_settings(|settings| settings.button_mapping = mapping);
+    }
+
+    /// Gates `stick` on capacitive touch, so drift can't move it while no thumb is on the cap.
+    pub fn set_stick_requires_touch(&self, stick: Stick, required: bool) {
+        self.shared.update_settings(|settings| match stick {
+            Stick::Left => settings.left_stick_requires_touch = required,
+            Stick::Right => settings.right_stick_requires_touch = required,
+        });
+    }
+
+    pub fn haptic_pulse(
+        &self,
+        pad: Trackpad,
+        duration_us: u16,
+        interval_us: u16,
+        count: u16,
+    ) -> Result<(), SteamDeckInputError> {
+        self.send_feature_report(&haptic_pulse_msg(pad, duration_us, interval_us, count))
+    }
+
+    pub fn calibration(&self) -> DeviceCalibration {
+        self.settings().calibration
+    }
+
+    /// Applies `calibration` and remembers it for the connected device's serial number. Set
+    /// while disconnected, it is remembered for the next device that connects.
+    pub fn set_calibration(&self, calibration: DeviceCalibration) {
+        self.shared.remember_calibration(calibration);
+        self.shared
+            .update_settings(|settings| settings.calibration = calibration);
+    }
+
+    pub fn calibrations(&self) -> HashMap<String, DeviceCalibration> {
+        self.shared.calibrations.lock().unwrap().clone()
+    }
+
+    /// Stores a calibration for a device that may or may not be connected yet.
+    pub fn set_device_calibration(&self, serial: &str, calibration: DeviceCalibration) {
+        self.shared
+            .calibrations
+            .lock()
+            .unwrap()
+            .insert(serial.to_string(), calibration);
+        if self.serial_number().as_deref() == Some(serial) {
+            self.shared
+                .update_settings(|settings| settings.calibration = calibration);
+        }
+    }
+
+    pub fn begin_calibration(&self) {
+        *self.shared.calibrator.lock().unwrap() = Some(Calibrator::new());
+        self.shared.calibrating.store(true, Ordering::Release);
+    }
+
+    pub fn set_calibration_step(&self, step: CalibrationStep) {
+        if let Some(calibrator) = &mut *self.shared.calibrator.lock().unwrap() {
+            calibrator.set_step(step);
+        }
+    }
+
+    pub fn cancel_calibration(&self) {
+        self.shared.calibrating.store(false, Ordering::Release);
+        *self.shared.calibrator.lock().unwrap() = None;
+    }
+
+    /// Ends the guided calibration and applies the result. Returns `None` (and keeps the
+    /// previous calibration) if not enough range was captured.
+    pub fn finish_calibration(&self) -> Option<DeviceCalibration> {
+        self.shared.calibrating.store(false, Ordering::Release);
+        let calibrator = self.shared.calibrator.lock().unwrap().take()?;
+        let calibration = calibrator.finish()?;
+        self.set_calibration(calibration);
+        Some(calibration)
+    }
+
+    pub fn calibrate_in_firmware(
+        &self,
+        calibration: FirmwareCalibration,
+    ) -> Result<(), SteamDeckInputError> {
+        let mut msg = FeatureReportMsg::zeroed();
+        msg.header.report_type = calibration.report_type();
+        self.send_feature_report(&msg)
+    }
+
+    pub fn recalibrate_trackpads(&self) -> Result<(), SteamDeckInputError> {
+        let mut msg = FeatureReportMsg::zeroed();
+        msg.header.report_type = FEATURE_REPORT_MESSAGE_ID_CALIBRATE_TRACKPADS;
+        self.send_feature_report(&msg)
+    }
+
+    pub fn trackpad_calibration(
+        &self,
+        pad: Trackpad,
+    ) -> Result<ValveControllerTrackpadImage, SteamDeckInputError> {
+        self.get_trackpad_image(FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_CALIBRATION, pad)
+    }
+
+    pub fn trackpad_factory_calibration(
+        &self,
+        pad: Trackpad,
+    ) -> Result<ValveControllerTrackpadImage, SteamDeckInputError> {
+        self.get_trackpad_image(
+            FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_FACTORY_CALIBRATION,
+            pad,
+        )
+    }
+
+    pub fn compare_trackpad_calibration(
+        &self,
+        pad: Trackpad,
+    ) -> Result<TrackpadCalibrationComparison, SteamDeckInputError> {
+        Ok(TrackpadCalibrationComparison {
+            pad,
+            current: self.trackpad_calibration(pad)?,
+            factory: self.trackpad_factory_calibration(pad)?,
+        })
+    }
+
+    /// Makes the input thread poll raw capacitive data for `pad` (or stop with `None`), one
+    /// chunk every 20 ms at most. Chunks are taken from the feature report reply, or from the
+    /// debug input report following it when the reply comes without one. Chunks are collected with `drain_trackpad_raw_data`.
+    pub fn stream_trackpad_raw_data(&self, pad: Option<Trackpad>) {
+        *self.shared.raw_stream.lock().unwrap() = pad;
+        self.shared.raw_chunks.lock().unwrap().clear();
+    }
+
+    pub fn drain_trackpad_raw_data(&self) -> Vec<TrackpadRawChunk> {
+        self.shared.raw_chunks.lock().unwrap().drain(..).collect()
+    }
+
+    fn get_trackpad_image(
+        &self,
+        report_type: u8,
+        pad: Trackpad,
+    ) -> Result<ValveControllerTrackpadImage, SteamDeckInputError> {
+        let mut msg = FeatureReportMsg::zeroed();
+        msg.header.report_type = report_type;
+        msg.header.report_length = mem::size_of::<MsgTrackpadRequest>() as u8;
+        msg.payload.trackpad_request = MsgTrackpadRequest {
+            pad_num: pad.pad_num(),
+            offset: 0,
+        };
+
+        let reply = self.get_feature_report(&msg)?;
+        Ok(unsafe { reply.payload.trackpad_image })
+    }
+
+    pub fn send_feature_report(&self, msg: &FeatureReportMsg) -> Result<(), SteamDeckInputError> {
+        self.device_request(msg, false).map(|_| ())
+    }
+
+    /// Sends `msg` and reads back the device's reply to it. A reply of another message type
+    /// is a `ProtocolError`.
+    pub fn get_feature_report(
+        &self,
+        msg: &FeatureReportMsg,
+    ) -> Result<FeatureReportMsg, SteamDeckInputError> {
+        let reply = self.device_request(msg, true)?;
+        Ok(*from_bytes::<FeatureReportMsg>(
+            &reply[1..(1 + mem::size_of::<FeatureReportMsg>())],
+        ))
+    }
+
+    fn device_request(
+        &self,
+        msg: &FeatureReportMsg,
+        read_reply: bool,
+    ) -> Result<FeatureReportBuf, SteamDeckInputError> {
+        if !self.shared.found.load(Ordering::SeqCst) {
+            return Err(SteamDeckInputError::NotConnected);
+        }
+
+        let report = feature_report_buf(msg);
+
+        let (reply, result) = mpsc::channel();
+        let status = Arc::new(AtomicU8::new(REQUEST_PENDING));
+        self.shared.requests.lock().unwrap().push(DeviceRequest {
+            report,
+            read_reply,
+            status: status.clone(),
+            reply,
+        });
+
+        match result.recv_timeout(REQUEST_TIMEOUT) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(SteamDeckInputError::NotConnected),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let cancelled = status.compare_exchange(
+                    REQUEST_PENDING,
+                    REQUEST_CANCELLED,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                if cancelled.is_ok() {
+                    return Err(SteamDeckInputError::Timeout);
+                }
+                // Already being sent; its result is on the way
+                result
+                    .recv()
+                    .unwrap_or(Err(SteamDeckInputError::NotConnected))
+            }
         }
     }
 }
@@ -138,6 +1370,10 @@ impl Drop for SteamdeckInput {
         if let Some(thread) = self.thread.take() {
             thread.join().ok();
         }
+        // Ends once the input thread dropped its sender
+        if let Some(thread) = self.callback_thread.take() {
+            thread.join().ok();
+        }
     }
 }
 
@@ -145,6 +1381,13 @@ impl Drop for SteamdeckInput {
 pub enum SteamDeckInputError {
     HidError(HidError),
     ProtocolError(String),
+    NotConnected,
+    /// The input thread did not get to a device request in time; it was not sent.
+    Timeout,
+    InvalidConfig(String),
+    ProfileError(String),
+    /// Spawning the input or callback thread failed.
+    ThreadError(io::Error),
 }
 
 impl From<HidError> for SteamDeckInputError {
@@ -153,76 +1396,401 @@ impl From<HidError> for SteamDeckInputError {
     }
 }
 
+impl From<io::Error> for SteamDeckInputError {
+    fn from(io_error: io::Error) -> Self {
+        SteamDeckInputError::ThreadError(io_error)
+    }
+}
+
 impl From<String> for SteamDeckInputError {
     fn from(protocol_error: String) -> Self {
         SteamDeckInputError::ProtocolError(protocol_error)
     }
 }
 
-const STEAMDECK_VID_PID: (u16, u16) = (0x28de, 0x1205);
+fn steamdeck_input_thread(shared: Arc<SteamdeckShared>, mut callback: Option<CallbackDispatch>) {
+    let mut stats = StatsCollector::default();
+    // Owned by this thread so consumers never hold it up; they see the published `GamepadState`
+    let mut state = {
+        let settings = shared.settings.lock().unwrap();
+        GamepadUpdateState::new(
+            settings.clone(),
+            shared.settings_generation.load(Ordering::Acquire),
+        )
+    };
 
-fn steamdeck_input_thread(shared: Arc<SteamdeckShared>) {
     'retry: while shared.run.load(Ordering::SeqCst) {
-        if let Err(e) = handle_steam_deck_device(&shared) {
+        if let Err(e) = handle_steam_deck_device(&shared, &mut state, &mut stats, callback.as_mut())
+        {
             log::error!("SteamDeckError: {e:?}");
         }
 
         shared.found.store(false, Ordering::SeqCst);
-        for _ in 0..100 {
+        shared.wake_consumers();
+        shared.requests.lock().unwrap().clear();
+        let retry_at = Instant::now() + shared.config.retry_interval;
+        while let Some(remaining) = retry_at.checked_duration_since(Instant::now()) {
             if !shared.run.load(Ordering::SeqCst) {
                 continue 'retry;
             }
-            thread::sleep(Duration::from_millis(16));
+            thread::sleep(remaining.min(Duration::from_millis(16)));
         }
     }
 }
 
-fn handle_steam_deck_device(shared: &SteamdeckShared) -> Result<(), SteamDeckInputError> {
+fn handle_steam_deck_device(
+    shared: &SteamdeckShared,
+    state: &mut GamepadUpdateState,
+    stats: &mut StatsCollector,
+    mut callback: Option<&mut CallbackDispatch>,
+) -> Result<(), SteamDeckInputError> {
     let api = hidapi::HidApi::new().unwrap();
+    let config = &shared.config;
 
-    let Some(device) = ({
-        let mut device = None;
-
-        for device_info in api.device_list() {
-            if device_info.vendor_id() == STEAMDECK_VID_PID.0
-                && device_info.product_id() == STEAMDECK_VID_PID.1
-                && device_info.interface_number() == 2
-            {
-                device = Some(device_info.open_device(&api)?);
-            }
-        }
-        device
-    }) else {
+    let Some(found) = enumerate::find_device(&api, config) else {
         // Not finding a device is not an error
         return Ok(());
     };
+    let device = found.open(&api).inspect_err(|_| {
+        log::warn!(
+            "Could not open {}; diagnostics::diagnose explains permission problems",
+            found.path
+        )
+    })?;
+    let serial = found.serial_number;
+
+    {
+        // A calibration set while disconnected belongs to this device; otherwise use the one
+        // saved for it, or keep the current one if there is none
+        let pending = shared.pending_calibration.lock().unwrap().take();
+        match (&serial, pending) {
+            (Some(serial), Some(calibration)) => {
+                shared
+                    .calibrations
+                    .lock()
+                    .unwrap()
+                    .insert(serial.clone(), calibration);
+            }
+            (Some(serial), None) => {
+                let saved = shared.calibrations.lock().unwrap().get(serial).copied();
+                if let Some(calibration) = saved {
+                    shared.update_settings(|settings| settings.calibration = calibration);
+                }
+            }
+            (None, _) => {}
+        }
+        state.sync_settings(shared);
+        state.gamepad.packet = PacketInfo::default();
+        // So the previous connection's last packet isn't fetched as this one's
+        // Safety: this thread is the only writer
+        unsafe { shared.gamepad.write(state.gamepad) };
+        shared.history.lock().unwrap().clear();
+        *shared.serial.lock().unwrap() = serial;
+    }
 
     shared.found.store(true, Ordering::SeqCst);
+    shared.wake_consumers();
 
-    disable_deck_lizard_mode(&device)?;
+    let mut lizard_mode = state.settings.lizard_mode;
+    if let LizardMode::Disable { .. } = lizard_mode {
+        disable_deck_lizard_mode(&device)?;
+    }
 
     let mut lizard_counter = 0;
+    let mut raw_stream: Option<RawDataStream> = None;
+    let mut last_stats_log = Instant::now();
+    let mut events = Vec::new();
+    let mut history = Vec::new();
 
     while shared.run.load(Ordering::SeqCst) {
         let mut buf = [0u8; 64];
-        let read = device.read_timeout(&mut buf[..], 16)?;
-        if read > 0 {
-            let report = from_bytes::<ValveInReport>(&buf[..read]).to_deck_state()?;
-            shared.state.lock().unwrap().update(&report);
-        } else {
-            return Err("Read returned wrong size".to_string().into());
+        let read = device.read_timeout(&mut buf[..], config.read_timeout_ms())?;
+        let received = Instant::now();
+
+        if shared.reset_stats.swap(false, Ordering::SeqCst) {
+            *stats = StatsCollector::default();
+        }
+
+        let report = match read {
+            0 => {
+                // A stall, not a disconnect; a device that is gone makes the read itself fail
+                stats.on_read_timeout();
+                if let Ok(mut shared_stats) = shared.stats.try_lock() {
+                    *shared_stats = stats.stats();
+                }
+                None
+            }
+            read if read != mem::size_of::<ValveInReport>() => {
+                return Err(format!("Read returned wrong size: {read}").into());
+            }
+            _ => {
+                let report = from_bytes::<ValveInReport>(&buf[..read]);
+                if report.header.report_type == VALVE_IN_REPORT_MESSAGE_ID_CONTROLLER_DECK_STATE {
+                    Some(report.to_deck_state()?)
+                } else {
+                    match (raw_stream.as_mut(), report.to_raw_pad_image()) {
+                        (Some(stream), Some(image)) if stream.accepts(&image) => {
+                            push_raw_chunk(shared, stream, image, received);
+                        }
+                        _ => stats.on_other_report(),
+                    }
+                    None
+                }
+            }
+        };
+
+        if let Some(report) = report {
+            if !state.sync_settings(shared) {
+                stats.on_lock_contended();
+            }
+            if shared.reset_heading.swap(false, Ordering::AcqRel) {
+                state.fusion.reset_heading();
+            }
+            if shared.calibrating.load(Ordering::Acquire) {
+                if let Ok(mut calibrator) = shared.calibrator.try_lock() {
+                    if let Some(calibrator) = &mut *calibrator {
+                        calibrator.sample(&report);
+                    }
+                }
+            }
+
+            let first_event = events.len();
+            let haptic_tick = state.update(&report, received, &mut events);
+
+            if let Some(callback) = &mut callback {
+                let sample = DeckSample {
+                    packet: report,
+                    state: state.gamepad,
+                    events: events[first_event..].to_vec(),
+                };
+                callback.dispatch(sample, shared);
+            }
+
+            let publish_start = Instant::now();
+            // Safety: this thread is the only writer
+            unsafe { shared.gamepad.write(state.gamepad) };
+            stats.on_packet(received, publish_start.elapsed());
+
+            history.push(state.gamepad);
+            if !publish_bounded(&shared.history, &mut history, HISTORY_CAPACITY) {
+                stats.on_lock_contended();
+            }
+            if !publish_bounded(&shared.events, &mut events, MAX_QUEUED_EVENTS) {
+                stats.on_lock_contended();
+            }
+            shared.wake_consumers();
+            if let Some(pad) = haptic_tick {
+                // Cosmetic, so a failed tick must not cost the connection
+                let msg = haptic_pulse_msg(pad, HAPTIC_TICK_DURATION_US, 0, 1);
+                if let Err(e) = device.send_feature_report(&feature_report_buf(&msg)) {
+                    log::debug!("Haptic tick failed: {e:?}");
+                }
+            }
+        }
+
+        if stats.packets().is_multiple_of(STATS_PUBLISH_PACKETS) {
+            if let Ok(mut shared_stats) = shared.stats.try_lock() {
+                *shared_stats = stats.stats();
+            }
         }
 
-        lizard_counter += 1;
-        if lizard_counter > 200 {
-            lizard_counter = 0;
-            disable_deck_lizard_mode(&device)?;
+        let log_interval = shared.stats_log_interval_ms.load(Ordering::Relaxed);
+        if log_interval > 0 && last_stats_log.elapsed() >= Duration::from_millis(log_interval) {
+            last_stats_log = Instant::now();
+            log::info!("SteamDeck input stats: {:?}", shared.stats());
+        }
+
+        let requests = shared
+            .requests
+            .try_lock()
+            .map(|mut requests| mem::take(&mut *requests))
+            .unwrap_or_default();
+        for request in requests {
+            let started = request.status.compare_exchange(
+                REQUEST_PENDING,
+                REQUEST_STARTED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+            if started.is_err() {
+                // The caller gave up on it
+                continue;
+            }
+            let result = feature_request(&device, &request.report, request.read_reply);
+            request.reply.send(result).ok();
+        }
+
+        if let Ok(stream_pad) = shared.raw_stream.try_lock() {
+            if raw_stream.as_ref().map(|s| s.pad) != *stream_pad {
+                raw_stream = stream_pad.map(RawDataStream::new);
+            }
+        }
+        if let Some(stream) = raw_stream.as_mut().filter(|stream| stream.due(received)) {
+            // Only a diagnostic, so a failure must not cost the connection
+            match get_trackpad_raw_data(&device, stream.pad, stream.next_offset) {
+                Ok(Some(image)) => push_raw_chunk(shared, stream, image, received),
+                Ok(None) => stream.sent(received),
+                Err(e) => {
+                    stats.on_raw_data_error();
+                    if stream.fail(received) {
+                        log::warn!("Trackpad raw data request failed: {e:?}");
+                    }
+                }
+            }
+        }
+
+        match (lizard_mode, state.settings.lizard_mode) {
+            (LizardMode::Keep, LizardMode::Disable { .. }) => disable_deck_lizard_mode(&device)?,
+            (LizardMode::Disable { .. }, LizardMode::Keep) => restore_deck_lizard_mode(&device)?,
+            _ => {}
+        }
+        lizard_mode = state.settings.lizard_mode;
+
+        if let LizardMode::Disable {
+            keepalive_packets, ..
+        } = lizard_mode
+        {
+            lizard_counter += 1;
+            if lizard_counter > keepalive_packets {
+                lizard_counter = 0;
+                disable_deck_lizard_mode(&device)?;
+            }
         }
     }
 
+    if let LizardMode::Disable {
+        restore_on_exit: true,
+        ..
+    } = lizard_mode
+    {
+        restore_deck_lizard_mode(&device)?;
+    }
+
     Ok(())
 }
 
+/// Moves `pending` into `queue`, dropping the oldest beyond `capacity`. Items wait in `pending`
+/// while a consumer holds the queue, rather than stalling input; then this returns false.
+fn publish_bounded<T>(queue: &Mutex<VecDeque<T>>, pending: &mut Vec<T>, capacity: usize) -> bool {
+    if pending.is_empty() {
+        return true;
+    }
+    if let Ok(mut queue) = queue.try_lock() {
+        for item in pending.drain(..) {
+            if queue.len() >= capacity {
+                queue.pop_front();
+            }
+            queue.push_back(item);
+        }
+        true
+    } else {
+        let excess = pending.len().saturating_sub(capacity);
+        pending.drain(..excess);
+        false
+    }
+}
+
+fn haptic_pulse_msg(
+    pad: Trackpad,
+    duration_us: u16,
+    interval_us: u16,
+    count: u16,
+) -> FeatureReportMsg {
+    let mut msg = FeatureReportMsg::zeroed();
+    msg.header.report_type = FEATURE_REPORT_MESSAGE_ID_TRIGGER_HAPTIC_PULSE;
+    msg.header.report_length = mem::size_of::<MsgFireHapticPulse>() as u8;
+    msg.payload.fire_haptic_pulse = MsgFireHapticPulse {
+        which_pad: pad.pad_num(),
+        pulse_duration: duration_us,
+        pulse_interval: interval_us,
+        pulse_count: count,
+        db_gain: 0,
+        priority: 0,
+    };
+    msg
+}
+
+fn feature_report_buf(msg: &FeatureReportMsg) -> FeatureReportBuf {
+    let mut buf = [0u8; HID_FEATURE_REPORT_BYTES + 1];
+    buf[1..(1 + mem::size_of::<FeatureReportMsg>())].copy_from_slice(bytes_of(msg));
+    buf
+}
+
+fn push_raw_chunk(
+    shared: &SteamdeckShared,
+    stream: &mut RawDataStream,
+    image: ValveControllerRawTrackpadImage,
+    now: Instant,
+) {
+    stream.advance(&image, now);
+
+    if let Ok(mut chunks) = shared.raw_chunks.try_lock() {
+        if chunks.len() >= MAX_QUEUED_RAW_CHUNKS {
+            chunks.pop_front();
+        }
+        chunks.push_back(TrackpadRawChunk {
+            pad: stream.pad,
+            received: Instant::now(),
+            image,
+        });
+    }
+}
+
+/// `None` if the device acknowledged the request without the image, which then arrives as a
+/// debug input report.
+fn get_trackpad_raw_data(
+    device: &HidDevice,
+    pad: Trackpad,
+    offset: u8,
+) -> Result<Option<ValveControllerRawTrackpadImage>, SteamDeckInputError> {
+    let mut msg = FeatureReportMsg::zeroed();
+    msg.header.report_type = FEATURE_REPORT_MESSAGE_ID_GET_TRACKPAD_RAW_DATA;
+    msg.header.report_length = mem::size_of::<MsgTrackpadRequest>() as u8;
+    msg.payload.trackpad_request = MsgTrackpadRequest {
+        pad_num: pad.pad_num(),
+        offset,
+    };
+
+    let reply = feature_request(device, &feature_report_buf(&msg), true)?;
+    let reply = from_bytes::<FeatureReportMsg>(&reply[1..(1 + mem::size_of::<FeatureReportMsg>())]);
+    if (reply.header.report_length as usize) < mem::size_of::<ValveControllerRawTrackpadImage>() {
+        return Ok(None);
+    }
+    Ok(Some(unsafe { reply.payload.raw_trackpad_image }))
+}
+
+fn feature_request(
+    device: &HidDevice,
+    report: &FeatureReportBuf,
+    read_reply: bool,
+) -> Result<FeatureReportBuf, SteamDeckInputError> {
+    device.send_feature_report(&report[..])?;
+
+    let mut buf = [0u8; HID_FEATURE_REPORT_BYTES + 1];
+    if read_reply {
+        let read = device.get_feature_report(&mut buf[..])?;
+        if read < 1 + mem::size_of::<FeatureReportHeader>() {
+            return Err("Feature report reply too short".to_string().into());
+        }
+        // Byte 0 is the report id, byte 1 the message type
+        if buf[1] != report[1] {
+            return Err(format!(
+                "Feature report reply has type {:#x}, expected {:#x}",
+                buf[1], report[1]
+            )
+            .into());
+        }
+    }
+
+    Ok(buf)
+}
+
+fn restore_deck_lizard_mode(device: &HidDevice) -> HidResult<()> {
+    let mut msg = FeatureReportMsg::zeroed();
+    msg.header.report_type = FEATURE_REPORT_MESSAGE_ID_SET_DEFAULT_DIGITAL_MAPPINGS;
+    device.send_feature_report(&feature_report_buf(&msg))
+}
+
 fn disable_deck_lizard_mode(device: &HidDevice) -> HidResult<()> {
     {
         let mut buf = [0u8; HID_FEATURE_REPORT_BYTES + 1];
@@ -255,3 +1823,104 @@ fn disable_deck_lizard_mode(device: &HidDevice) -> HidResult<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet_info(packet_nums: &[u32]) -> PacketInfo {
+        let mut state = GamepadUpdateState::new(InputSettings::default(), 0);
+        let start = Instant::now();
+        for (i, &packet_num) in packet_nums.iter().enumerate() {
+            state.update_packet_info(packet_num, start + Duration::from_millis(4 * i as u64));
+        }
+        state.gamepad.packet
+    }
+
+    #[test]
+    fn packet_info_counts_nothing_for_increments() {
+        let info = packet_info(&[1, 2, 3]);
+        assert_eq!(info.packet_num, 3);
+        assert_eq!(info.interval, Duration::from_millis(4));
+        assert_eq!((info.dropped, info.duplicated), (0, 0));
+    }
+
+    #[test]
+    fn packet_info_counts_gaps() {
+        let info = packet_info(&[1, 5, 6, 8]);
+        assert_eq!((info.dropped, info.duplicated), (4, 0));
+    }
+
+    #[test]
+    fn packet_info_counts_duplicates() {
+        let info = packet_info(&[7, 7, 8, 8]);
+        assert_eq!((info.dropped, info.duplicated), (0, 2));
+    }
+
+    #[test]
+    fn packet_info_wraps_past_u32_max() {
+        let info = packet_info(&[u32::MAX - 1, u32::MAX, 0, 2]);
+        assert_eq!((info.dropped, info.duplicated), (1, 0));
+    }
+
+    fn packet(packet_num: u32, buttons: u64) -> SteamDeckStatePacket {
+        SteamDeckStatePacket {
+            packet_num,
+            buttons,
+            ..Zeroable::zeroed()
+        }
+    }
+
+    #[test]
+    fn presses_between_fetches_are_counted() {
+        let mut state = GamepadUpdateState::new(InputSettings::default(), 0);
+        let mut events = Vec::new();
+        let start = Instant::now();
+        let mut update = |packet_num: u32, buttons: u64| {
+            let now = start + Duration::from_millis(4 * packet_num as u64);
+            state.update(&packet(packet_num, buttons), now, &mut events);
+            state.gamepad
+        };
+
+        let previous = update(1, BUTTON_A);
+        update(2, 0);
+        let current = update(3, BUTTON_A);
+
+        assert_eq!(current.buttons[0], 1);
+        assert_eq!(current.press_count(&previous, 0), 1);
+        assert_eq!(current.release_count(&previous, 0), 1);
+        assert!(current.just_pressed(&previous, 0));
+        assert!(!current.just_pressed(&previous, 1));
+    }
+
+    #[test]
+    fn press_counts_wrap() {
+        let mut previous = GamepadState::default();
+        let mut current = GamepadState::default();
+        previous.presses[0] = u32::MAX;
+        current.presses[0] = 1;
+        assert_eq!(current.press_count(&previous, 0), 2);
+    }
+
+    #[test]
+    fn mask_counts_count_edges() {
+        let mut counts = MaskCounts::default();
+        counts.update(0, 0b101);
+        counts.update(0b101, 0b100);
+        counts.update(0b100, 0b100);
+        assert_eq!((counts.presses(0), counts.releases(0)), (1, 1));
+        assert_eq!((counts.presses(2), counts.releases(2)), (1, 0));
+        assert_eq!((counts.presses(1), counts.releases(1)), (0, 0));
+
+        counts.presses[63] = u32::MAX;
+        counts.update(0, 1 << 63);
+        assert_eq!(counts.presses(63), 0);
+    }
+
+    #[test]
+    fn packet_info_ignores_counter_restarts() {
+        let info = packet_info(&[1000, 3, 4]);
+        assert_eq!(info.packet_num, 4);
+        assert_eq!((info.dropped, info.duplicated), (0, 0));
+    }
+}
@@ -0,0 +1,524 @@
+use std::time::{Duration, Instant};
+
+/// How long the buttons of a chord are held back waiting for the rest of the chord.
+pub const CHORD_WINDOW: Duration = Duration::from_millis(50);
+
+/// Rules between the packet's button mask and `GamepadState::buttons`. Masks use the
+/// `protocol::BUTTON_*` bits; outputs may also use bits no physical button has, to expose
+/// actions through `GamepadState::mapped_buttons`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ButtonMapping {
+    /// The first layer is the base layer, the others take priority over it (and the later
+    /// ones over earlier ones) while active.
+    pub layers: Vec<MappingLayer>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MappingLayer {
+    /// Buttons that activate the layer while held; they are not passed through. The base
+    /// layer ignores this.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+    pub modifier: u64,
+    /// Pressing `modifier` switches the layer on and off instead.
+    pub toggle: bool,
+    pub rules: Vec<MappingRule>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MappingRule {
+    /// While all of `from` are held, output `to` instead.
+    ///
+    /// With several buttons in `from` this is a chord. Its buttons are held back for
+    /// `CHORD_WINDOW` after the first one is pressed. If the rest follow within that time only
+    /// `to` is output, and the buttons stay swallowed until all are released. Otherwise they
+    /// pass through once the window is over, and a button tapped within it is passed on as
+    /// a press of a single update.
+    Remap {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        from: u64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        to: u64,
+    },
+    /// Each press of `button` switches `output` on or off.
+    Toggle {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        button: u64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        output: u64,
+    },
+    /// While `button` is held it is pressed and released every `period`. A press of
+    /// `enable` switches the turbo on and off; with no `enable` it is always on.
+    Turbo {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        button: u64,
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        enable: u64,
+        #[cfg_attr(
+            feature = "serde",
+            serde(rename = "period_ms", with = "crate::serde_format::millis")
+        )]
+        period: Duration,
+    },
+    /// A press of `trigger` plays `steps` once.
+    Macro {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+        trigger: u64,
+        steps: Vec<MacroStep>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MacroStep {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_format::button_mask"))]
+    pub output: u64,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "duration_ms", with = "crate::serde_format::millis")
+    )]
+    pub duration: Duration,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct RuleState {
+    held: bool,
+    enable_held: bool,
+    on: bool,
+    since: Option<Instant>,
+    /// Chord buttons held back during the chord window.
+    pending: u64,
+}
+
+/// Runs a `ButtonMapping`. Only depends on the masks and times passed to `update`.
+#[derive(Clone, Debug, Default)]
+pub struct ButtonMapper {
+    mapping: ButtonMapping,
+    layers: Vec<(bool, bool)>,
+    rules: Vec<Vec<RuleState>>,
+}
+
+impl ButtonMapper {
+    pub fn new(mapping: ButtonMapping) -> ButtonMapper {
+        ButtonMapper {
+            layers: vec![(false, false); mapping.layers.len()],
+            rules: mapping
+                .layers
+                .iter()
+                .map(|layer| vec![RuleState::default(); layer.rules.len()])
+                .collect(),
+            mapping,
+        }
+    }
+
+    pub fn mapping(&self) -> &ButtonMapping {
+        &self.mapping
+    }
+
+    /// Whether layer `index` is active after the latest update.
+    pub fn layer_active(&self, index: usize) -> bool {
+        index == 0 || self.layers.get(index).is_some_and(|&(active, _)| active)
+    }
+
+    pub fn update(&mut self, buttons: u64, time: Instant) -> u64 {
+        let mut remaining = buttons;
+
+        for (layer, (active, modifier_held)) in
+            self.mapping.layers.iter().zip(&mut self.layers).skip(1)
+        {
+            let held = held(buttons, layer.modifier);
+            if layer.toggle {
+                if held && !*modifier_held {
+                    *active = !*active;
+                }
+            } else {
+                *active = held;
+            }
+            *modifier_held = held;
+            if held {
+                remaining &= !layer.modifier;
+            }
+        }
+
+        let mut output = 0;
+        for (index, layer) in self.mapping.layers.iter().enumerate().rev() {
+            if index > 0 && !self.layers[index].0 {
+                // Inactive layers must not see presses that started elsewhere as new ones
+                for state in &mut self.rules[index] {
+                    state.held = false;
+                    state.enable_held = false;
+                    state.pending = 0;
+                }
+                continue;
+            }
+            for (rule, state) in layer.rules.iter().zip(&mut self.rules[index]) {
+                output |= apply_rule(rule, state, &mut remaining, time);
+            }
+        }
+
+        output | remaining
+    }
+}
+
+fn held(buttons: u64, mask: u64) -> bool {
+    mask != 0 && buttons & mask == mask
+}
+
+fn apply_rule(
+    rule: &MappingRule,
+    state: &mut RuleState,
+    remaining: &mut u64,
+    time: Instant,
+) -> u64 {
+    match *rule {
+        MappingRule::Remap { from, to } if from.count_ones() > 1 => {
+            apply_chord(from, to, state, remaining, time)
+        }
+        MappingRule::Remap { from, to } => {
+            if held(*remaining, from) {
+                *remaining &= !from;
+                to
+            } else {
+                0
+            }
+        }
+        MappingRule::Toggle { button, output } => {
+            let pressed = held(*remaining, button);
+            if pressed && !state.held {
+                state.on = !state.on;
+            }
+            state.held = pressed;
+            *remaining &= !button;
+            if state.on {
+                output
+            } else {
+                0
+            }
+        }
+        MappingRule::Turbo {
+            button,
+            enable,
+            period,
+        } => {
+            if enable == 0 {
+                state.on = true;
+            } else {
+                let enable_pressed = held(*remaining, enable);
+                if enable_pressed && !state.enable_held {
+                    state.on = !state.on;
+                }
+                state.enable_held = enable_pressed;
+                *remaining &= !enable;
+            }
+
+            let pressed = held(*remaining, button);
+            if pressed && !state.held {
+                state.since = Some(time);
+            }
+            state.held = pressed;
+            if !state.on || !pressed {
+                return 0;
+            }
+
+            *remaining &= !button;
+            let elapsed = time.duration_since(state.since.unwrap_or(time));
+            let half_period = (period / 2).max(Duration::from_micros(1));
+            if (elapsed.as_micros() / half_period.as_micros()).is_multiple_of(2) {
+                button
+            } else {
+                0
+            }
+        }
+        MappingRule::Macro { trigger, ref steps } => {
+            let pressed = held(*remaining, trigger);
+            if pressed && !state.held {
+                state.since = Some(time);
+            }
+            state.held = pressed;
+            *remaining &= !trigger;
+
+            let Some(start) = state.since else {
+                return 0;
+            };
+            let mut step_end = start;
+            for step in steps {
+                step_end += step.duration;
+                if time < step_end {
+                    return step.output;
+                }
+            }
+            state.since = None;
+            0
+        }
+    }
+}
+
+fn apply_chord(
+    from: u64,
+    to: u64,
+    state: &mut RuleState,
+    remaining: &mut u64,
+    time: Instant,
+) -> u64 {
+    let members = *remaining & from;
+
+    if state.on {
+        *remaining &= !from;
+        if members == 0 {
+            state.on = false;
+            state.since = None;
+        }
+        return if members == from { to } else { 0 };
+    }
+
+    // Buttons released while held back were taps; pass them on for this update
+    let taps = state.pending & !members;
+    state.pending &= members;
+
+    if members == 0 {
+        state.since = None;
+        return taps;
+    }
+    let since = *state.since.get_or_insert(time);
+    if time.duration_since(since) >= CHORD_WINDOW {
+        state.pending = 0;
+        return taps;
+    }
+
+    *remaining &= !from;
+    if members == from {
+        state.on = true;
+        state.pending = 0;
+        to
+    } else {
+        state.pending |= members;
+        taps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{BUTTON_A, BUTTON_B, BUTTON_L4, BUTTON_R4, BUTTON_X, BUTTON_Y};
+
+    const OUTPUT: u64 = 1 << 60;
+
+    fn mapper(layers: Vec<MappingLayer>) -> ButtonMapper {
+        ButtonMapper::new(ButtonMapping { layers })
+    }
+
+    fn base(rules: Vec<MappingRule>) -> MappingLayer {
+        MappingLayer {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn chord_engages_and_releases() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Remap {
+            from: BUTTON_A | BUTTON_B,
+            to: OUTPUT,
+        }])]);
+        let t = Instant::now();
+
+        // The first button is held back while waiting for the second
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 0)), 0);
+        assert_eq!(mapper.update(BUTTON_A | BUTTON_B, ms(t, 20)), OUTPUT);
+        assert_eq!(mapper.update(BUTTON_A | BUTTON_B, ms(t, 500)), OUTPUT);
+
+        // Releasing one button ends the chord without passing the other through
+        assert_eq!(mapper.update(BUTTON_B, ms(t, 520)), 0);
+        assert_eq!(mapper.update(0, ms(t, 540)), 0);
+
+        assert_eq!(mapper.update(BUTTON_B, ms(t, 1000)), 0);
+        assert_eq!(mapper.update(BUTTON_A | BUTTON_B, ms(t, 1010)), OUTPUT);
+    }
+
+    #[test]
+    fn chord_button_passes_through_after_window() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Remap {
+            from: BUTTON_A | BUTTON_B,
+            to: OUTPUT,
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 0)), 0);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 40)), 0);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 60)), BUTTON_A);
+        // Too late for the chord
+        assert_eq!(
+            mapper.update(BUTTON_A | BUTTON_B, ms(t, 80)),
+            BUTTON_A | BUTTON_B
+        );
+        assert_eq!(mapper.update(0, ms(t, 100)), 0);
+    }
+
+    #[test]
+    fn chord_button_tap_is_passed_on() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Remap {
+            from: BUTTON_A | BUTTON_B,
+            to: OUTPUT,
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 0)), 0);
+        assert_eq!(mapper.update(0, ms(t, 20)), BUTTON_A);
+        assert_eq!(mapper.update(0, ms(t, 40)), 0);
+    }
+
+    #[test]
+    fn single_button_remap() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Remap {
+            from: BUTTON_A,
+            to: BUTTON_B,
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_A | BUTTON_X, t), BUTTON_B | BUTTON_X);
+        assert_eq!(mapper.update(BUTTON_X, t), BUTTON_X);
+    }
+
+    #[test]
+    fn layer_shift_while_modifier_held() {
+        let mut mapper = mapper(vec![
+            base(vec![]),
+            MappingLayer {
+                modifier: BUTTON_L4,
+                toggle: false,
+                rules: vec![MappingRule::Remap {
+                    from: BUTTON_A,
+                    to: BUTTON_Y,
+                }],
+            },
+        ]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_A, t), BUTTON_A);
+        assert!(!mapper.layer_active(1));
+        // The modifier itself is not passed through
+        assert_eq!(mapper.update(BUTTON_L4 | BUTTON_A, t), BUTTON_Y);
+        assert!(mapper.layer_active(1));
+        assert_eq!(mapper.update(BUTTON_A, t), BUTTON_A);
+        assert!(!mapper.layer_active(1));
+    }
+
+    #[test]
+    fn toggled_layer() {
+        let mut mapper = mapper(vec![
+            base(vec![]),
+            MappingLayer {
+                modifier: BUTTON_R4,
+                toggle: true,
+                rules: vec![MappingRule::Remap {
+                    from: BUTTON_A,
+                    to: BUTTON_Y,
+                }],
+            },
+        ]);
+        let t = Instant::now();
+
+        mapper.update(BUTTON_R4, t);
+        mapper.update(0, t);
+        assert!(mapper.layer_active(1));
+        assert_eq!(mapper.update(BUTTON_A, t), BUTTON_Y);
+
+        mapper.update(BUTTON_R4, t);
+        mapper.update(0, t);
+        assert!(!mapper.layer_active(1));
+        assert_eq!(mapper.update(BUTTON_A, t), BUTTON_A);
+    }
+
+    #[test]
+    fn toggle_rule() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Toggle {
+            button: BUTTON_X,
+            output: OUTPUT,
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(0, t), 0);
+        assert_eq!(mapper.update(BUTTON_X, t), OUTPUT);
+        assert_eq!(mapper.update(BUTTON_X, t), OUTPUT);
+        assert_eq!(mapper.update(0, t), OUTPUT);
+        assert_eq!(mapper.update(BUTTON_X, t), 0);
+        assert_eq!(mapper.update(0, t), 0);
+    }
+
+    #[test]
+    fn turbo_period() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Turbo {
+            button: BUTTON_A,
+            enable: 0,
+            period: Duration::from_millis(100),
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 0)), BUTTON_A);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 49)), BUTTON_A);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 50)), 0);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 99)), 0);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 100)), BUTTON_A);
+        assert_eq!(mapper.update(0, ms(t, 120)), 0);
+    }
+
+    #[test]
+    fn turbo_enable_toggles() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Turbo {
+            button: BUTTON_A,
+            enable: BUTTON_R4,
+            period: Duration::from_millis(100),
+        }])]);
+        let t = Instant::now();
+
+        // Off: the button passes through unchanged
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 0)), BUTTON_A);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 60)), BUTTON_A);
+        mapper.update(BUTTON_R4, ms(t, 100));
+        mapper.update(0, ms(t, 120));
+
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 200)), BUTTON_A);
+        assert_eq!(mapper.update(BUTTON_A, ms(t, 260)), 0);
+    }
+
+    #[test]
+    fn macro_plays_steps_once() {
+        let mut mapper = mapper(vec![base(vec![MappingRule::Macro {
+            trigger: BUTTON_L4,
+            steps: vec![
+                MacroStep {
+                    output: BUTTON_A,
+                    duration: Duration::from_millis(30),
+                },
+                MacroStep {
+                    output: 0,
+                    duration: Duration::from_millis(20),
+                },
+                MacroStep {
+                    output: BUTTON_B,
+                    duration: Duration::from_millis(30),
+                },
+            ],
+        }])]);
+        let t = Instant::now();
+
+        assert_eq!(mapper.update(BUTTON_L4, ms(t, 0)), BUTTON_A);
+        // Keeps playing after the trigger is released
+        assert_eq!(mapper.update(0, ms(t, 29)), BUTTON_A);
+        assert_eq!(mapper.update(0, ms(t, 30)), 0);
+        assert_eq!(mapper.update(0, ms(t, 50)), BUTTON_B);
+        assert_eq!(mapper.update(0, ms(t, 79)), BUTTON_B);
+        assert_eq!(mapper.update(0, ms(t, 80)), 0);
+        assert_eq!(mapper.update(0, ms(t, 200)), 0);
+
+        assert_eq!(mapper.update(BUTTON_L4, ms(t, 300)), BUTTON_A);
+    }
+}
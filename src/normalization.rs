@@ -0,0 +1,41 @@
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TriggerRange {
+    /// Released at 0, fully pulled at 1.
+    ZeroToOne,
+    /// Released at -1, fully pulled at 1.
+    #[default]
+    MinusOneToOne,
+    /// The uncalibrated `trigger_raw_*` value.
+    Raw,
+}
+
+impl TriggerRange {
+    pub fn apply(self, calibrated: f32, raw: u16) -> f32 {
+        match self {
+            TriggerRange::ZeroToOne => calibrated,
+            TriggerRange::MinusOneToOne => calibrated * 2.0 - 1.0,
+            TriggerRange::Raw => raw as f32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Normalization {
+    pub trigger_range: TriggerRange,
+    /// By default every exposed Y axis (sticks, trackpads, gyro stick) is positive downwards;
+    /// this makes them positive upwards.
+    pub invert_y: bool,
+}
+
+impl Normalization {
+    pub fn y(&self, down_positive: f32) -> f32 {
+        if self.invert_y {
+            -down_positive
+        } else {
+            down_positive
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{trackpad::TrackpadSample, InputEventKind, Trackpad};
+
+pub const DPAD_UP: u64 = 1;
+pub const DPAD_RIGHT: u64 = 2;
+pub const DPAD_DOWN: u64 = 4;
+pub const DPAD_LEFT: u64 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DPadDirections {
+    Four,
+    Eight,
+}
+
+/// Virtual buttons produced by a mode are reported as a bitmask: the `DPAD_*` bits, the
+/// hovered slice index of a radial menu, or `row * columns + column` of a grid cell.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrackpadMode {
+    DPad {
+        directions: DPadDirections,
+        /// Degrees by which neighbouring directions overlap, so diagonals press both.
+        overlap: f32,
+        deadzone: f32,
+        require_click: bool,
+    },
+    RadialMenu {
+        /// At most 64, one bit each.
+        slices: u8,
+        deadzone: f32,
+        /// Select with a pad click instead of lifting the finger.
+        select_on_click: bool,
+    },
+    /// At most 64 cells, one bit each: `columns` is capped at 64 and `rows` at
+    /// `64 / columns`, so larger grids lose their bottom rows.
+    ButtonGrid {
+        rows: u8,
+        columns: u8,
+        require_click: bool,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TrackpadModeState {
+    pad: Trackpad,
+    mode: TrackpadMode,
+    hovered: Option<usize>,
+    previous_clicked: bool,
+}
+
+impl TrackpadModeState {
+    pub fn new(pad: Trackpad, mode: TrackpadMode) -> TrackpadModeState {
+        TrackpadModeState {
+            pad,
+            mode,
+            hovered: None,
+            previous_clicked: false,
+        }
+    }
+
+    pub fn pad(&self) -> Trackpad {
+        self.pad
+    }
+
+    pub fn mode(&self) -> TrackpadMode {
+        self.mode
+    }
+
+    /// Returns the virtual button bitmask for this packet.
+    pub fn update(
+        &mut self,
+        sample: &TrackpadSample,
+        clicked: bool,
+        events: &mut Vec<InputEventKind>,
+    ) -> u64 {
+        let click_edge = clicked && !self.previous_clicked;
+        self.previous_clicked = clicked;
+
+        let [x, y] = sample.position;
+        let radius = (x * x + y * y).sqrt();
+        // Clockwise from straight up, 0..360
+        let angle = x.atan2(-y).to_degrees().rem_euclid(360.0);
+
+        match self.mode {
+            TrackpadMode::DPad {
+                directions,
+                overlap,
+                deadzone,
+                require_click,
+            } => {
+                let active = if require_click {
+                    clicked
+                } else {
+                    sample.touched
+                };
+                if !active || radius < deadzone {
+                    return 0;
+                }
+
+                let half_width = match directions {
+                    DPadDirections::Four => 45.0,
+                    DPadDirections::Eight => 67.5,
+                } + overlap * 0.5;
+
+                let mut buttons = 0;
+                for (i, bit) in [DPAD_UP, DPAD_RIGHT, DPAD_DOWN, DPAD_LEFT]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let center = i as f32 * 90.0;
+                    let distance = (angle - center + 180.0).rem_euclid(360.0) - 180.0;
+                    if distance.abs() < half_width {
+                        buttons |= bit;
+                    }
+                }
+                buttons
+            }
+            TrackpadMode::RadialMenu {
+                slices,
+                deadzone,
+                select_on_click,
+            } => {
+                let slices = slices.clamp(1, 64) as usize;
+
+                let hovered = if sample.touched && radius >= deadzone {
+                    let width = 360.0 / slices as f32;
+                    Some(((angle + width * 0.5) / width) as usize % slices)
+                } else {
+                    None
+                };
+
+                let selected = if select_on_click {
+                    hovered.filter(|_| click_edge)
+                } else if !sample.touched {
+                    self.hovered
+                } else {
+                    None
+                };
+                if let Some(slice) = selected {
+                    events.push(InputEventKind::RadialMenuSelected {
+                        pad: self.pad,
+                        slice,
+                    });
+                }
+
+                self.hovered = hovered;
+                hovered.map(|slice| 1 << slice).unwrap_or(0)
+            }
+            TrackpadMode::ButtonGrid {
+                rows,
+                columns,
+                require_click,
+            } => {
+                let active = if require_click {
+                    clicked
+                } else {
+                    sample.touched
+                };
+                if !active {
+                    return 0;
+                }
+
+                let columns = columns.clamp(1, 64) as usize;
+                let rows = (rows as usize).clamp(1, 64 / columns);
+                let column = (((x + 1.0) * 0.5 * columns as f32) as usize).min(columns - 1);
+                let row = (((y + 1.0) * 0.5 * rows as f32) as usize).min(rows - 1);
+
+                1 << (row * columns + column)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> TrackpadSample {
+        TrackpadSample {
+            touched: true,
+            position: [x, y],
+            pressure: 0,
+        }
+    }
+
+    fn lifted() -> TrackpadSample {
+        TrackpadSample {
+            touched: false,
+            position: [0.0, 0.0],
+            pressure: 0,
+        }
+    }
+
+    fn dpad(directions: DPadDirections, require_click: bool) -> TrackpadModeState {
+        TrackpadModeState::new(
+            Trackpad::Left,
+            TrackpadMode::DPad {
+                directions,
+                overlap: 0.0,
+                deadzone: 0.2,
+                require_click,
+            },
+        )
+    }
+
+    fn update(mode: &mut TrackpadModeState, sample: TrackpadSample, clicked: bool) -> u64 {
+        let mut events = Vec::new();
+        let buttons = mode.update(&sample, clicked, &mut events);
+        assert_eq!(events, []);
+        buttons
+    }
+
+    #[test]
+    fn dpad_four_directions() {
+        let mut mode = dpad(DPadDirections::Four, false);
+        // Positive y is down
+        assert_eq!(update(&mut mode, at(0.0, -0.8), false), DPAD_UP);
+        assert_eq!(update(&mut mode, at(0.8, 0.1), false), DPAD_RIGHT);
+        assert_eq!(update(&mut mode, at(0.1, 0.8), false), DPAD_DOWN);
+        assert_eq!(update(&mut mode, at(-0.8, 0.0), false), DPAD_LEFT);
+        assert_eq!(update(&mut mode, at(0.1, 0.1), false), 0);
+        assert_eq!(update(&mut mode, lifted(), false), 0);
+    }
+
+    #[test]
+    fn dpad_eight_directions_press_diagonals() {
+        let mut mode = dpad(DPadDirections::Eight, false);
+        assert_eq!(
+            update(&mut mode, at(0.6, -0.6), false),
+            DPAD_UP | DPAD_RIGHT
+        );
+        assert_eq!(
+            update(&mut mode, at(-0.6, 0.6), false),
+            DPAD_DOWN | DPAD_LEFT
+        );
+        assert_eq!(update(&mut mode, at(0.0, -0.8), false), DPAD_UP);
+    }
+
+    #[test]
+    fn dpad_require_click() {
+        let mut mode = dpad(DPadDirections::Four, true);
+        assert_eq!(update(&mut mode, at(0.0, -0.8), false), 0);
+        assert_eq!(update(&mut mode, at(0.0, -0.8), true), DPAD_UP);
+    }
+
+    #[test]
+    fn radial_menu_selects_on_lift() {
+        let mut mode = TrackpadModeState::new(
+            Trackpad::Right,
+            TrackpadMode::RadialMenu {
+                slices: 8,
+                deadzone: 0.3,
+                select_on_click: false,
+            },
+        );
+        let mut events = Vec::new();
+
+        // Slices are numbered clockwise from straight up
+        assert_eq!(mode.update(&at(0.0, -0.9), false, &mut events), 1 << 0);
+        assert_eq!(mode.update(&at(0.9, 0.0), false, &mut events), 1 << 2);
+        assert_eq!(mode.update(&at(-0.6, -0.6), false, &mut events), 1 << 7);
+        assert_eq!(events, []);
+
+        assert_eq!(mode.update(&lifted(), false, &mut events), 0);
+        assert_eq!(
+            events,
+            [InputEventKind::RadialMenuSelected {
+                pad: Trackpad::Right,
+                slice: 7
+            }]
+        );
+
+        // Lifting inside the deadzone selects nothing
+        events.clear();
+        mode.update(&at(0.1, 0.0), false, &mut events);
+        mode.update(&lifted(), false, &mut events);
+        assert_eq!(events, []);
+    }
+
+    #[test]
+    fn radial_menu_selects_on_click() {
+        let mut mode = TrackpadModeState::new(
+            Trackpad::Left,
+            TrackpadMode::RadialMenu {
+                slices: 4,
+                deadzone: 0.3,
+                select_on_click: true,
+            },
+        );
+        let mut events = Vec::new();
+
+        mode.update(&at(0.0, 0.9), false, &mut events);
+        mode.update(&at(0.0, 0.9), true, &mut events);
+        // Only the click edge selects
+        mode.update(&at(0.0, 0.9), true, &mut events);
+        mode.update(&lifted(), false, &mut events);
+        assert_eq!(
+            events,
+            [InputEventKind::RadialMenuSelected {
+                pad: Trackpad::Left,
+                slice: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn button_grid_cells() {
+        let mut mode = TrackpadModeState::new(
+            Trackpad::Left,
+            TrackpadMode::ButtonGrid {
+                rows: 2,
+                columns: 3,
+                require_click: false,
+            },
+        );
+        assert_eq!(update(&mut mode, at(-0.9, -0.9), false), 1 << 0);
+        assert_eq!(update(&mut mode, at(0.0, -0.5), false), 1 << 1);
+        assert_eq!(update(&mut mode, at(1.0, 1.0), false), 1 << 5);
+        assert_eq!(update(&mut mode, at(-0.5, 0.5), false), 1 << 3);
+        assert_eq!(update(&mut mode, lifted(), false), 0);
+    }
+
+    #[test]
+    fn button_grid_clamped_to_64_cells() {
+        let mut mode = TrackpadModeState::new(
+            Trackpad::Left,
+            TrackpadMode::ButtonGrid {
+                rows: 10,
+                columns: 10,
+                require_click: false,
+            },
+        );
+        // Six rows fit; the bottom of the pad is the last of them
+        assert_eq!(update(&mut mode, at(-1.0, 1.0), false), 1 << 50);
+        assert_eq!(update(&mut mode, at(1.0, 1.0), false), 1 << 59);
+    }
+}
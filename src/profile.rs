@@ -0,0 +1,254 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::LizardMode, fusion::FusionFilter, gesture::GestureConfig, gyro::GyroAimConfig,
+    mapping::ButtonMapping, normalization::Normalization, pad_modes::TrackpadMode,
+    response::StickResponse, trackpad::TrackpadMouseConfig, trigger::TriggerThresholds,
+    InputSettings, SteamDeckInputError, SteamdeckInput,
+};
+
+/// A control profile, e.g. one per vehicle, stored as TOML or JSON. Fields missing from a
+/// file keep their defaults. Calibration is per device and not part of a profile.
+/// Durations are stored as `*_ms` milliseconds and button masks as hex strings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub left_stick: StickResponse,
+    pub right_stick: StickResponse,
+    pub left_stick_requires_touch: bool,
+    pub right_stick_requires_touch: bool,
+    pub trigger_thresholds: TriggerThresholds,
+    pub normalization: Normalization,
+    pub fusion_filter: FusionFilter,
+    pub gyro_aim: GyroAimConfig,
+    pub trackpad_mouse: Option<TrackpadMouseConfig>,
+    pub left_pad_mode: Option<TrackpadMode>,
+    pub right_pad_mode: Option<TrackpadMode>,
+    pub left_gestures: Option<GestureConfig>,
+    pub right_gestures: Option<GestureConfig>,
+    pub lizard_mode: LizardMode,
+    pub button_mapping: ButtonMapping,
+}
+
+impl Profile {
+    pub fn from_settings(settings: &InputSettings) -> Profile {
+        Profile {
+            left_stick: settings.left_stick_response,
+            right_stick: settings.right_stick_response,
+            left_stick_requires_touch: settings.left_stick_requires_touch,
+            right_stick_requires_touch: settings.right_stick_requires_touch,
+            trigger_thresholds: settings.trigger_thresholds,
+            normalization: settings.normalization,
+            fusion_filter: settings.fusion_filter,
+            gyro_aim: settings.gyro_aim,
+            trackpad_mouse: settings.trackpad_mouse,
+            left_pad_mode: settings.left_pad_mode,
+            right_pad_mode: settings.right_pad_mode,
+            left_gestures: settings.left_gestures,
+            right_gestures: settings.right_gestures,
+            lizard_mode: settings.lizard_mode,
+            button_mapping: settings.button_mapping.clone(),
+        }
+    }
+
+    pub fn apply_to(&self, settings: &mut InputSettings) {
+        settings.left_stick_response = self.left_stick;
+        settings.right_stick_response = self.right_stick;
+        settings.left_stick_requires_touch = self.left_stick_requires_touch;
+        settings.right_stick_requires_touch = self.right_stick_requires_touch;
+        settings.trigger_thresholds = self.trigger_thresholds;
+        settings.normalization = self.normalization;
+        settings.fusion_filter = self.fusion_filter;
+        settings.gyro_aim = self.gyro_aim;
+        settings.trackpad_mouse = self.trackpad_mouse;
+        settings.left_pad_mode = self.left_pad_mode;
+        settings.right_pad_mode = self.right_pad_mode;
+        settings.left_gestures = self.left_gestures;
+        settings.right_gestures = self.right_gestures;
+        settings.lizard_mode = self.lizard_mode;
+        settings.button_mapping = self.button_mapping.clone();
+    }
+
+    pub fn validate(&self) -> Result<(), SteamDeckInputError> {
+        self.left_stick.validate()?;
+        self.right_stick.validate()
+    }
+
+    pub fn from_toml(text: &str) -> Result<Profile, SteamDeckInputError> {
+        let profile: Profile =
+            toml::from_str(text).map_err(|e| SteamDeckInputError::ProfileError(e.to_string()))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn from_json(text: &str) -> Result<Profile, SteamDeckInputError> {
+        let profile: Profile = serde_json::from_str(text)
+            .map_err(|e| SteamDeckInputError::ProfileError(e.to_string()))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn to_toml(&self) -> Result<String, SteamDeckInputError> {
+        toml::to_string_pretty(self).map_err(|e| SteamDeckInputError::ProfileError(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, SteamDeckInputError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| SteamDeckInputError::ProfileError(e.to_string()))
+    }
+
+    /// Reads a `.json` file as JSON and anything else as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Profile, SteamDeckInputError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| SteamDeckInputError::ProfileError(format!("{}: {e}", path.display())))?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Profile::from_json(&text)
+        } else {
+            Profile::from_toml(&text)
+        }
+    }
+}
+
+impl SteamdeckInput {
+    pub fn profile(&self) -> Profile {
+        Profile::from_settings(&self.settings())
+    }
+
+    /// Takes effect with the next packet, so it can be swapped while input is running.
+    pub fn apply_profile(&self, profile: &Profile) {
+        self.shared
+            .update_settings(|settings| profile.apply_to(settings));
+    }
+}
+
+/// Reloads a profile file when its modification time changes.
+pub struct ProfileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ProfileWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> ProfileWatcher {
+        ProfileWatcher {
+            path: path.into(),
+            modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The profile if the file changed since the last successful load (the first call always
+    /// loads). A file that fails to parse is retried once it changes again.
+    pub fn poll(&mut self) -> Result<Option<Profile>, SteamDeckInputError> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| {
+                SteamDeckInputError::ProfileError(format!("{}: {e}", self.path.display()))
+            })?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+
+        self.modified = Some(modified);
+        Profile::load(&self.path).map(Some)
+    }
+
+    /// Applies the profile to `input` if the file changed. Returns whether it did.
+    pub fn reload(&mut self, input: &SteamdeckInput) -> Result<bool, SteamDeckInputError> {
+        match self.poll()? {
+            Some(profile) => {
+                input.apply_profile(&profile);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        gyro::GyroActivation,
+        mapping::{MacroStep, MappingLayer, MappingRule},
+        protocol::{BUTTON_A, BUTTON_L4, BUTTON_QUICK_ACCESS},
+    };
+
+    fn profile() -> Profile {
+        let mut profile = Profile::default();
+        profile.gyro_aim.activation = GyroActivation::WhileHeld(BUTTON_L4);
+        profile.left_gestures = Some(GestureConfig::default());
+        profile.button_mapping.layers = vec![MappingLayer {
+            rules: vec![
+                MappingRule::Remap {
+                    from: BUTTON_QUICK_ACCESS,
+                    to: 1 << 63,
+                },
+                MappingRule::Turbo {
+                    button: BUTTON_A,
+                    enable: 0,
+                    period: Duration::from_millis(80),
+                },
+                MappingRule::Macro {
+                    trigger: BUTTON_L4,
+                    steps: vec![MacroStep {
+                        output: BUTTON_A,
+                        duration: Duration::from_millis(30),
+                    }],
+                },
+            ],
+            ..Default::default()
+        }];
+        profile
+    }
+
+    #[test]
+    fn toml_round_trip_with_high_button_bits() {
+        let profile = profile();
+        let text = profile.to_toml().unwrap();
+        assert!(text.contains("to = \"0x8000000000000000\""), "{text}");
+        assert!(text.contains("period_ms = 80"), "{text}");
+        assert!(text.contains("tap_max_duration_ms = 200"), "{text}");
+        assert_eq!(Profile::from_toml(&text).unwrap(), profile);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let profile = profile();
+        assert_eq!(
+            Profile::from_json(&profile.to_json().unwrap()).unwrap(),
+            profile
+        );
+    }
+
+    #[test]
+    fn masks_accept_integers_and_hex() {
+        let profile = Profile::from_toml(
+            "[[button_mapping.layers]]\n\
+             modifier = 8\n\
+             rules = [{ Toggle = { button = \"0x80\", output = \"0X4_0000\" } }]\n",
+        )
+        .unwrap();
+        let layer = &profile.button_mapping.layers[0];
+        assert_eq!(layer.modifier, 8);
+        assert_eq!(
+            layer.rules,
+            [MappingRule::Toggle {
+                button: BUTTON_A,
+                output: 0x40000
+            }]
+        );
+        assert!(Profile::from_toml("[[button_mapping.layers]]\nmodifier = -1\n").is_err());
+    }
+}
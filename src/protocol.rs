@@ -208,6 +208,14 @@ impl ValveInReport {
 
         Ok(unsafe { self.payload.deck_state })
     }
+
+    /// The payload of a debug report as a raw trackpad image. Debug reports don't say what
+    /// they carry, so this only makes sense while raw data was requested.
+    pub fn to_raw_pad_image(&self) -> Option<ValveControllerRawTrackpadImage> {
+        (self.header.report_version == VALVE_IN_REPORT_MSG_VERSION
+            && self.header.report_type == VALVE_IN_REPORT_MESSAGE_ID_CONTROLLER_DEBUG)
+            .then_some(unsafe { self.payload.raw_pad_image })
+    }
 }
 
 pub const HID_FEATURE_REPORT_BYTES: usize = 64;
@@ -359,6 +367,15 @@ pub struct MsgSimpleRumbleCmd {
 
 const_assert_eq!(mem::size_of::<MsgSimpleRumbleCmd>(), 9);
 
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug, Zeroable, Pod)]
+pub struct MsgTrackpadRequest {
+    pub pad_num: u8,
+    pub offset: u8,
+}
+
+const_assert_eq!(mem::size_of::<MsgTrackpadRequest>(), 2);
+
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub union FeatureReportMsgPayload {
@@ -374,6 +391,9 @@ pub union FeatureReportMsgPayload {
     pub haptic_hode: MsgHapticSetMode,
     pub trigger_haptic: MsgTriggerHaptic,
     pub simple_rumble: MsgSimpleRumbleCmd,
+    pub trackpad_request: MsgTrackpadRequest,
+    pub trackpad_image: ValveControllerTrackpadImage,
+    pub raw_trackpad_image: ValveControllerRawTrackpadImage,
 }
 
 const_assert_eq!(mem::size_of::<FeatureReportMsgPayload>(), 60);
@@ -464,3 +484,5 @@ pub const RIGHT_STICK_USED: u64 = 0x800000000000;
 pub const LEFT_STICK_USED: u64 = 0x400000000000;
 pub const BUTTON_LEFT_PAD: u64 = 0x00020000;
 pub const BUTTON_RIGHT_PAD: u64 = 0x00040000;
+pub const BUTTON_LEFT_PAD_TOUCH: u64 = 0x00080000;
+pub const BUTTON_RIGHT_PAD_TOUCH: u64 = 0x00100000;
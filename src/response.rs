@@ -0,0 +1,111 @@
+use crate::SteamDeckInputError;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResponseCurve {
+    #[default]
+    Linear,
+    /// Deflection raised to this power; above 1 gives finer control near the center. Must be
+    /// positive and finite, otherwise it is applied as `Linear`.
+    Power(f32),
+}
+
+impl ResponseCurve {
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            ResponseCurve::Power(exponent) if exponent > 0.0 && exponent.is_finite() => {
+                value.powf(exponent)
+            }
+            _ => value,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SteamDeckInputError> {
+        match self {
+            ResponseCurve::Power(exponent) if !(*exponent > 0.0 && exponent.is_finite()) => {
+                Err(SteamDeckInputError::InvalidConfig(format!(
+                    "response curve exponent must be positive and finite, not {exponent}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Radial deadzones and response curve of a stick, applied after calibration.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StickResponse {
+    /// Deflection below which the stick reports centered, 0..1.
+    pub deadzone: f32,
+    /// Deflection this close to the edge already reports full, 0..1.
+    pub outer_deadzone: f32,
+    pub curve: ResponseCurve,
+}
+
+impl StickResponse {
+    /// Each axis of the result is within -1..1.
+    pub fn apply(&self, stick: [f32; 2]) -> [f32; 2] {
+        let magnitude = stick[0].hypot(stick[1]);
+        if magnitude <= self.deadzone {
+            return [0.0; 2];
+        }
+
+        let range = (1.0 - self.deadzone - self.outer_deadzone).max(f32::EPSILON);
+        let mut scaled = (magnitude - self.deadzone) / range;
+        // Without an outer deadzone the square gate's corners keep reaching past 1, so the
+        // axes still reach their ends towards the corners
+        if self.outer_deadzone > 0.0 {
+            scaled = scaled.min(1.0);
+        }
+        let scale = self.curve.apply(scaled) / magnitude;
+        [
+            (stick[0] * scale).clamp(-1.0, 1.0),
+            (stick[1] * scale).clamp(-1.0, 1.0),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), SteamDeckInputError> {
+        self.curve.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(deadzone: f32, curve: ResponseCurve) -> StickResponse {
+        StickResponse {
+            deadzone,
+            outer_deadzone: 0.0,
+            curve,
+        }
+    }
+
+    #[test]
+    fn diagonal_stays_within_range() {
+        for curve in [ResponseCurve::Linear, ResponseCurve::Power(2.0)] {
+            assert_eq!(response(0.2, curve).apply([1.0, 1.0]), [1.0, 1.0]);
+            assert_eq!(response(0.2, curve).apply([-1.0, 1.0]), [-1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn axes_reach_full_deflection() {
+        let response = response(0.2, ResponseCurve::Linear);
+        assert_eq!(response.apply([1.0, 0.0]), [1.0, 0.0]);
+        assert_eq!(response.apply([0.0, -1.0]), [0.0, -1.0]);
+        assert_eq!(response.apply([0.1, 0.1]), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn invalid_exponents_are_rejected() {
+        for exponent in [-1.0, 0.0, f32::NAN, f32::INFINITY] {
+            let response = response(0.0, ResponseCurve::Power(exponent));
+            assert!(response.validate().is_err());
+            assert!(response.apply([0.0, 0.5])[1].is_finite());
+        }
+        assert!(response(0.0, ResponseCurve::Power(1.5)).validate().is_ok());
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    cell::UnsafeCell,
+    hint,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+/// Single writer, many readers. Readers never block the writer; they retry if they
+/// overlapped a write.
+pub(crate) struct SeqLock<T> {
+    seq: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub fn new(value: T) -> SeqLock<T> {
+        SeqLock {
+            seq: AtomicUsize::new(0),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 != 0 {
+                hint::spin_loop();
+                continue;
+            }
+
+            // May be torn; only interpreted once the sequence shows no write overlapped
+            let value = unsafe { ptr::read_volatile(self.data.get() as *const MaybeUninit<T>) };
+            fence(Ordering::Acquire);
+
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return unsafe { value.assume_init() };
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Must only ever be called from one thread at a time.
+    pub unsafe fn write(&self, value: T) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        ptr::write_volatile(self.data.get(), value);
+
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the packet interval histogram buckets; the last bucket is everything above.
+pub const INTERVAL_BUCKETS: [Duration; 9] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(3),
+    Duration::from_millis(4),
+    Duration::from_millis(5),
+    Duration::from_millis(6),
+    Duration::from_millis(8),
+    Duration::from_millis(12),
+    Duration::from_millis(16),
+];
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputStats {
+    pub packets: u64,
+    /// Packets per second over the last full second.
+    pub packet_rate: f32,
+    pub interval_mean: Duration,
+    /// Standard deviation of the packet interval.
+    pub interval_jitter: Duration,
+    pub interval_max: Duration,
+    pub interval_histogram: [u64; INTERVAL_BUCKETS.len() + 1],
+    /// Reads that returned no data within the read timeout.
+    pub read_timeouts: u64,
+    /// Input reports other than controller state and requested raw trackpad data, e.g.
+    /// status reports; skipped.
+    pub other_reports: u64,
+    /// Failed raw trackpad data requests, see `SteamdeckInput::stream_trackpad_raw_data`.
+    pub raw_data_errors: u64,
+    /// Time the input thread spent publishing a state to consumers. It never waits for them,
+    /// so this is the cost of copying the state.
+    pub publish_time_total: Duration,
+    pub publish_time_max: Duration,
+    /// Times the input thread found the settings, history or event queue locked by a
+    /// consumer and left the work for a later packet.
+    pub lock_contention: u64,
+    pub fetches: u64,
+    /// Age of the newest packet when `fetch` returned it.
+    pub fetch_age_mean: Duration,
+    pub fetch_age_max: Duration,
+    /// Panics caught from the packet callback.
+    pub callback_panics: u64,
+    /// Samples dropped because a `CallbackThread::Dedicated` callback fell behind.
+    pub callback_drops: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StatsCollector {
+    stats: InputStats,
+    last_packet: Option<Instant>,
+    interval_count: u64,
+    interval_mean: f64,
+    interval_m2: f64,
+    window_start: Option<Instant>,
+    window_packets: u64,
+}
+
+impl StatsCollector {
+    pub fn on_packet(&mut self, received: Instant, publish_time: Duration) {
+        let stats = &mut self.stats;
+        stats.packets += 1;
+        stats.publish_time_total += publish_time;
+        stats.publish_time_max = stats.publish_time_max.max(publish_time);
+
+        if let Some(last) = self.last_packet {
+            let interval = received.duration_since(last);
+            let bucket = INTERVAL_BUCKETS
+                .iter()
+                .position(|bound| interval <= *bound)
+                .unwrap_or(INTERVAL_BUCKETS.len());
+            stats.interval_histogram[bucket] += 1;
+            stats.interval_max = stats.interval_max.max(interval);
+
+            // Welford's online variance
+            let secs = interval.as_secs_f64();
+            self.interval_count += 1;
+            let delta = secs - self.interval_mean;
+            self.interval_mean += delta / self.interval_count as f64;
+            self.interval_m2 += delta * (secs - self.interval_mean);
+
+            stats.interval_mean = Duration::from_secs_f64(self.interval_mean);
+            stats.interval_jitter =
+                Duration::from_secs_f64((self.interval_m2 / self.interval_count as f64).sqrt());
+        }
+        self.last_packet = Some(received);
+
+        let window_start = *self.window_start.get_or_insert(received);
+        self.window_packets += 1;
+        let window = received.duration_since(window_start);
+        if window >= RATE_WINDOW {
+            stats.packet_rate = self.window_packets as f32 / window.as_secs_f32();
+            self.window_start = Some(received);
+            self.window_packets = 0;
+        }
+    }
+
+    pub fn on_read_timeout(&mut self) {
+        self.stats.read_timeouts += 1;
+    }
+
+    pub fn on_other_report(&mut self) {
+        self.stats.other_reports += 1;
+    }
+
+    pub fn on_raw_data_error(&mut self) {
+        self.stats.raw_data_errors += 1;
+    }
+
+    pub fn on_lock_contended(&mut self) {
+        self.stats.lock_contention += 1;
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.stats.packets
+    }
+
+    pub fn stats(&self) -> InputStats {
+        self.stats
+    }
+}
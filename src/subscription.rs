@@ -0,0 +1,102 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{FetchedState, GamepadState, SteamdeckInput, SteamdeckShared, VirtualButtons};
+
+/// A consumer with its own view of the input: button edges and `mouse_delta` are relative to
+/// its own previous fetch, so consumers don't take them from each other. Fetching through a
+/// subscription doesn't affect `SteamdeckInput::fetch`.
+pub struct Subscription {
+    shared: Arc<SteamdeckShared>,
+    previous: GamepadState,
+    current: GamepadState,
+    stale_timeout: Duration,
+}
+
+impl SteamdeckInput {
+    /// Edges start from the moment of subscribing. The stale timeout starts out as
+    /// `SteamdeckInput::stale_timeout`.
+    pub fn subscribe(&self) -> Subscription {
+        let state = self.shared.gamepad.read();
+        Subscription {
+            shared: self.shared.clone(),
+            previous: state,
+            current: state,
+            stale_timeout: self.stale_timeout(),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn stale_timeout(&self) -> Duration {
+        self.stale_timeout
+    }
+
+    pub fn set_stale_timeout(&mut self, timeout: Duration) {
+        self.stale_timeout = timeout;
+    }
+
+    /// The latest state, or `None` if it is stale or no device is connected.
+    pub fn fetch(&mut self) -> Option<GamepadState> {
+        self.fetch_with_age()
+            .filter(|fetched| !fetched.stale)
+            .map(|fetched| fetched.state)
+    }
+
+    /// Like `SteamdeckInput::fetch_with_age`, with `mouse_delta` covering the time since this
+    /// subscription's previous fetch.
+    pub fn fetch_with_age(&mut self) -> Option<FetchedState> {
+        let mut fetched = self.shared.snapshot(self.stale_timeout)?;
+        let state = &mut fetched.state;
+
+        self.previous = self.current;
+        self.current = *state;
+
+        state.mouse_delta = [
+            (state.mouse_total[0] - self.previous.mouse_total[0]) as f32,
+            (state.mouse_total[1] - self.previous.mouse_total[1]) as f32,
+        ];
+
+        if fetched.connected {
+            self.shared.record_fetch_age(fetched.age);
+        }
+        Some(fetched)
+    }
+
+    /// Presses of `buttons[button]` between the two latest fetches.
+    pub fn press_count(&self, button: usize) -> u32 {
+        self.current.press_count(&self.previous, button)
+    }
+
+    pub fn release_count(&self, button: usize) -> u32 {
+        self.current.release_count(&self.previous, button)
+    }
+
+    pub fn just_pressed(&self, button: usize) -> bool {
+        self.current.just_pressed(&self.previous, button)
+    }
+
+    pub fn just_released(&self, button: usize) -> bool {
+        self.current.just_released(&self.previous, button)
+    }
+
+    /// Presses of `bit` of a virtual button mask between the two latest fetches.
+    pub fn virtual_press_count(&self, buttons: VirtualButtons, bit: usize) -> u32 {
+        self.current
+            .virtual_press_count(&self.previous, buttons, bit)
+    }
+
+    pub fn virtual_release_count(&self, buttons: VirtualButtons, bit: usize) -> u32 {
+        self.current
+            .virtual_release_count(&self.previous, buttons, bit)
+    }
+
+    pub fn virtual_just_pressed(&self, buttons: VirtualButtons, bit: usize) -> bool {
+        self.current
+            .virtual_just_pressed(&self.previous, buttons, bit)
+    }
+
+    pub fn virtual_just_released(&self, buttons: VirtualButtons, bit: usize) -> bool {
+        self.current
+            .virtual_just_released(&self.previous, buttons, bit)
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    io,
+    thread::{self, JoinHandle},
+};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ThreadPriority {
+    #[default]
+    Inherit,
+    /// Nice value, -20 (highest) to 19. Values below the current one need `CAP_SYS_NICE`.
+    Nice(i32),
+    /// Real-time `SCHED_FIFO` priority, 1 to 99. Needs `CAP_SYS_NICE` or an `RLIMIT_RTPRIO`.
+    Fifo(i32),
+}
+
+/// Scheduling of a thread the crate spawns. Priority and affinity are only supported on
+/// Linux; failing to apply them is logged and the thread runs with the defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ThreadConfig {
+    pub name: Option<String>,
+    pub priority: ThreadPriority,
+    /// CPUs the thread may run on, empty for any.
+    pub cpu_affinity: Vec<usize>,
+}
+
+impl ThreadConfig {
+    pub(crate) fn spawn(
+        &self,
+        default_name: &str,
+        f: impl FnOnce() + Send + 'static,
+    ) -> io::Result<JoinHandle<()>> {
+        let config = self.clone();
+        let name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| default_name.to_string());
+        thread::Builder::new().name(name).spawn(move || {
+            config.apply();
+            f()
+        })
+    }
+
+    fn apply(&self) {
+        if self.priority != ThreadPriority::Inherit {
+            if let Err(e) = set_priority(self.priority) {
+                log::warn!("Could not set thread priority {:?}: {e}", self.priority);
+            }
+        }
+        if !self.cpu_affinity.is_empty() {
+            if let Err(e) = set_affinity(&self.cpu_affinity) {
+                log::warn!("Could not set CPU affinity {:?}: {e}", self.cpu_affinity);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_priority(priority: ThreadPriority) -> io::Result<()> {
+    match priority {
+        ThreadPriority::Inherit => Ok(()),
+        ThreadPriority::Nice(nice) => {
+            // Linux applies nice per thread when given a thread id
+            let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+        ThreadPriority::Fifo(priority) => {
+            let param = libc::sched_param {
+                sched_priority: priority,
+            };
+            match unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            } {
+                0 => Ok(()),
+                e => Err(io::Error::from_raw_os_error(e)),
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("CPU {cpu} out of range"),
+            ));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_priority(_priority: ThreadPriority) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
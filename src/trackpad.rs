@@ -0,0 +1,288 @@
+use crate::{
+    protocol::{SteamDeckStatePacket, BUTTON_LEFT_PAD_TOUCH, BUTTON_RIGHT_PAD_TOUCH},
+    Trackpad,
+};
+
+// Below this speed (pad widths per second) momentum stops.
+const MIN_INERTIA_SPEED: f32 = 0.01;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TrackpadSample {
+    pub touched: bool,
+    /// -1..1, positive x right and positive y down.
+    pub position: [f32; 2],
+    pub pressure: u16,
+}
+
+impl TrackpadSample {
+    pub fn from_packet(packet: &SteamDeckStatePacket, pad: Trackpad) -> TrackpadSample {
+        let (touch, x, y, pressure) = match pad {
+            Trackpad::Left => (
+                BUTTON_LEFT_PAD_TOUCH,
+                packet.left_pad_x,
+                packet.left_pad_y,
+                packet.pressure_pad_left,
+            ),
+            Trackpad::Right => (
+                BUTTON_RIGHT_PAD_TOUCH,
+                packet.right_pad_x,
+                packet.right_pad_y,
+                packet.pressure_pad_right,
+            ),
+        };
+
+        TrackpadSample {
+            touched: packet.buttons & touch != 0,
+            position: [
+                (x as f32 / i16::MAX as f32).clamp(-1.0, 1.0),
+                -(y as f32 / i16::MAX as f32).clamp(-1.0, 1.0),
+            ],
+            pressure,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TrackpadMouseConfig {
+    pub pad: Trackpad,
+    /// Mouse counts per pad width.
+    pub sensitivity: f32,
+    /// Extra sensitivity multiplier reached at `acceleration_threshold` pad widths per second.
+    pub acceleration: f32,
+    pub acceleration_threshold: f32,
+    /// Keep the cursor moving after a flick, like a trackball.
+    pub inertia: bool,
+    /// Exponential decay rate of momentum, per second.
+    pub friction: f32,
+    /// Distance from the center (0..1) beyond which a resting finger keeps the cursor moving.
+    /// Zero disables edge spin.
+    pub edge_spin_radius: f32,
+    /// Mouse counts per second while spinning at the edge.
+    pub edge_spin_speed: f32,
+    /// Mouse counts between haptic ticks. Zero disables haptics.
+    pub haptic_distance: f32,
+}
+
+impl Default for TrackpadMouseConfig {
+    fn default() -> Self {
+        TrackpadMouseConfig {
+            pad: Trackpad::Right,
+            sensitivity: 500.0,
+            acceleration: 0.0,
+            acceleration_threshold: 4.0,
+            inertia: true,
+            friction: 4.0,
+            edge_spin_radius: 0.0,
+            edge_spin_speed: 500.0,
+            haptic_distance: 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TrackpadMouseOutput {
+    pub delta: [f32; 2],
+    pub haptic_tick: bool,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TrackpadMouse {
+    config: TrackpadMouseConfig,
+    previous: Option<[f32; 2]>,
+    /// Pad widths per second.
+    velocity: [f32; 2],
+    haptic_travel: f32,
+}
+
+impl TrackpadMouse {
+    pub fn new(config: TrackpadMouseConfig) -> TrackpadMouse {
+        TrackpadMouse {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> TrackpadMouseConfig {
+        self.config
+    }
+
+    pub fn update(&mut self, sample: &TrackpadSample, dt: f32) -> TrackpadMouseOutput {
+        let config = &self.config;
+        let mut delta = [0.0; 2];
+
+        if sample.touched {
+            if let Some(previous) = self.previous {
+                // Pad coordinates span 2 units per pad width.
+                let moved = [
+                    (sample.position[0] - previous[0]) * 0.5,
+                    (sample.position[1] - previous[1]) * 0.5,
+                ];
+                if dt > 0.0 {
+                    self.velocity = [moved[0] / dt, moved[1] / dt];
+                }
+
+                let mut sensitivity = config.sensitivity;
+                if config.acceleration_threshold > 0.0 {
+                    let ramp =
+                        (magnitude(self.velocity) / config.acceleration_threshold).clamp(0.0, 1.0);
+                    sensitivity *= 1.0 + config.acceleration * ramp;
+                }
+                delta = [moved[0] * sensitivity, moved[1] * sensitivity];
+            } else {
+                self.velocity = [0.0; 2];
+            }
+
+            let radius = magnitude(sample.position);
+            if config.edge_spin_radius > 0.0 && radius > config.edge_spin_radius {
+                let spin = config.edge_spin_speed * dt / radius;
+                delta[0] += sample.position[0] * spin;
+                delta[1] += sample.position[1] * spin;
+            }
+
+            self.previous = Some(sample.position);
+        } else {
+            self.previous = None;
+
+            if config.inertia && magnitude(self.velocity) > MIN_INERTIA_SPEED {
+                delta = [
+                    self.velocity[0] * config.sensitivity * dt,
+                    self.velocity[1] * config.sensitivity * dt,
+                ];
+                let decay = (-config.friction * dt).exp();
+                self.velocity = [self.velocity[0] * decay, self.velocity[1] * decay];
+            } else {
+                self.velocity = [0.0; 2];
+            }
+        }
+
+        let mut haptic_tick = false;
+        if config.haptic_distance > 0.0 {
+            self.haptic_travel += magnitude(delta);
+            if self.haptic_travel >= config.haptic_distance {
+                self.haptic_travel %= config.haptic_distance;
+                haptic_tick = true;
+            }
+        }
+
+        TrackpadMouseOutput { delta, haptic_tick }
+    }
+}
+
+fn magnitude(v: [f32; 2]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> TrackpadSample {
+        TrackpadSample {
+            touched: true,
+            position: [x, y],
+            pressure: 0,
+        }
+    }
+
+    fn lifted() -> TrackpadSample {
+        TrackpadSample::default()
+    }
+
+    fn config() -> TrackpadMouseConfig {
+        TrackpadMouseConfig {
+            sensitivity: 100.0,
+            inertia: false,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: [f32; 2], expected: [f32; 2]) {
+        assert!(
+            (actual[0] - expected[0]).abs() < 1e-3 && (actual[1] - expected[1]).abs() < 1e-3,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn delta_scales_with_sensitivity() {
+        let mut mouse = TrackpadMouse::new(config());
+        assert_close(mouse.update(&at(0.0, 0.0), 0.01).delta, [0.0, 0.0]);
+        // 0.2 in pad coordinates is a tenth of the pad width
+        assert_close(mouse.update(&at(0.2, -0.4), 0.01).delta, [10.0, -20.0]);
+    }
+
+    #[test]
+    fn acceleration_ramps_with_speed() {
+        let mut mouse = TrackpadMouse::new(TrackpadMouseConfig {
+            acceleration: 1.0,
+            acceleration_threshold: 4.0,
+            ..config()
+        });
+        mouse.update(&at(0.0, 0.0), 0.01);
+        // 10 pad widths per second, past the threshold
+        assert_close(mouse.update(&at(0.2, 0.0), 0.01).delta, [20.0, 0.0]);
+        // 0.1 pad widths per second, 1/40 of the threshold
+        assert_close(mouse.update(&at(0.4, 0.0), 1.0).delta, [10.25, 0.0]);
+    }
+
+    #[test]
+    fn momentum_decays_after_lift() {
+        let mut mouse = TrackpadMouse::new(TrackpadMouseConfig {
+            inertia: true,
+            friction: 4.0,
+            ..config()
+        });
+        mouse.update(&at(0.0, 0.0), 0.01);
+        mouse.update(&at(0.2, 0.0), 0.01);
+
+        assert_close(mouse.update(&lifted(), 0.01).delta, [10.0, 0.0]);
+        let decayed = 10.0 * (-0.04f32).exp();
+        assert_close(mouse.update(&lifted(), 0.01).delta, [decayed, 0.0]);
+
+        for _ in 0..500 {
+            mouse.update(&lifted(), 0.01);
+        }
+        assert_close(mouse.update(&lifted(), 0.01).delta, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn no_momentum_without_inertia() {
+        let mut mouse = TrackpadMouse::new(config());
+        mouse.update(&at(0.0, 0.0), 0.01);
+        mouse.update(&at(0.2, 0.0), 0.01);
+        assert_close(mouse.update(&lifted(), 0.01).delta, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn edge_spin_only_beyond_radius() {
+        let mut mouse = TrackpadMouse::new(TrackpadMouseConfig {
+            edge_spin_radius: 0.8,
+            edge_spin_speed: 500.0,
+            ..config()
+        });
+        mouse.update(&at(0.5, 0.0), 0.01);
+        assert_close(mouse.update(&at(0.5, 0.0), 0.01).delta, [0.0, 0.0]);
+
+        mouse.update(&at(0.0, -0.9), 0.01);
+        assert_close(mouse.update(&at(0.0, -0.9), 0.01).delta, [0.0, -5.0]);
+    }
+
+    #[test]
+    fn one_haptic_tick_per_distance() {
+        let mut mouse = TrackpadMouse::new(TrackpadMouseConfig {
+            haptic_distance: 12.5,
+            ..config()
+        });
+        mouse.update(&at(-1.0, 0.0), 0.01);
+        // 6.25 counts per update, so every second one ticks
+        let ticks: Vec<bool> = (1..=8)
+            .map(|i| {
+                let x = -1.0 + i as f32 * 0.125;
+                mouse.update(&at(x, 0.0), 0.01).haptic_tick
+            })
+            .collect();
+        assert_eq!(ticks, [false, true, false, true, false, true, false, true]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    protocol::{ValveControllerRawTrackpadImage, ValveControllerTrackpadImage},
+    Trackpad,
+};
+
+pub(crate) const MAX_QUEUED_RAW_CHUNKS: usize = 256;
+/// Each chunk costs a blocking feature report round trip on the input thread, so chunks are
+/// requested at most this often.
+pub(crate) const RAW_DATA_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Copy, Clone, Debug)]
+pub struct TrackpadCalibrationComparison {
+    pub pad: Trackpad,
+    pub current: ValveControllerTrackpadImage,
+    pub factory: ValveControllerTrackpadImage,
+}
+
+impl TrackpadCalibrationComparison {
+    pub fn deltas(&self) -> [i32; 20] {
+        let current = self.current.data;
+        let factory = self.factory.data;
+
+        let mut deltas = [0; 20];
+        for (delta, (current, factory)) in deltas.iter_mut().zip(current.iter().zip(factory)) {
+            *delta = *current as i32 - factory as i32;
+        }
+        deltas
+    }
+
+    pub fn max_abs_delta(&self) -> i32 {
+        self.deltas().iter().map(|d| d.abs()).max().unwrap_or(0)
+    }
+
+    pub fn noise_delta(&self) -> i32 {
+        self.current.noise as i32 - self.factory.noise as i32
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TrackpadRawChunk {
+    pub pad: Trackpad,
+    pub received: Instant,
+    pub image: ValveControllerRawTrackpadImage,
+}
+
+impl TrackpadRawChunk {
+    /// Index of the first sample in `image.data` within the full capacitive image.
+    pub fn offset(&self) -> usize {
+        self.image.offset as usize
+    }
+}
+
+pub(crate) struct RawDataStream {
+    pub pad: Trackpad,
+    pub next_offset: u8,
+    next_request: Option<Instant>,
+    failing: bool,
+    /// The last request was acknowledged without an image; the chunk follows as a debug
+    /// input report.
+    awaiting_report: bool,
+}
+
+impl RawDataStream {
+    pub fn new(pad: Trackpad) -> RawDataStream {
+        RawDataStream {
+            pad,
+            next_offset: 0,
+            next_request: None,
+            failing: false,
+            awaiting_report: false,
+        }
+    }
+
+    /// Whether the next chunk should be requested at `now`.
+    pub fn due(&self, now: Instant) -> bool {
+        self.next_request.is_none_or(|next| now >= next)
+    }
+
+    /// Returns whether this starts a run of failed requests, so only that one gets logged.
+    pub fn fail(&mut self, now: Instant) -> bool {
+        self.next_request = Some(now + RAW_DATA_INTERVAL);
+        self.awaiting_report = false;
+        !mem::replace(&mut self.failing, true)
+    }
+
+    /// The request was acknowledged without an image. If no debug report brings it before
+    /// the next request is due, the same offset is requested again.
+    pub fn sent(&mut self, now: Instant) {
+        self.next_request = Some(now + RAW_DATA_INTERVAL);
+        self.failing = false;
+        self.awaiting_report = true;
+    }
+
+    /// Whether a debug report's image answers the outstanding request.
+    pub fn accepts(&self, image: &ValveControllerRawTrackpadImage) -> bool {
+        self.awaiting_report && image.pad_num == self.pad.pad_num()
+    }
+
+    /// The image size is not reported by the device, so walk the offsets until the reply
+    /// no longer echoes the requested one and then start over.
+    pub fn advance(&mut self, image: &ValveControllerRawTrackpadImage, now: Instant) {
+        self.next_request = Some(now + RAW_DATA_INTERVAL);
+        self.failing = false;
+        self.awaiting_report = false;
+        let data = image.data;
+        let samples = data.len() as u8;
+        self.next_offset = if image.offset == self.next_offset {
+            self.next_offset.checked_add(samples).unwrap_or(0)
+        } else {
+            0
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    fn image(pad: Trackpad, offset: u8) -> ValveControllerRawTrackpadImage {
+        ValveControllerRawTrackpadImage {
+            pad_num: pad.pad_num(),
+            offset,
+            ..Zeroable::zeroed()
+        }
+    }
+
+    #[test]
+    fn debug_reports_answer_acknowledged_requests() {
+        let now = Instant::now();
+        let mut stream = RawDataStream::new(Trackpad::Right);
+        assert!(!stream.accepts(&image(Trackpad::Right, 0)));
+
+        stream.sent(now);
+        assert!(!stream.due(now));
+        assert!(!stream.accepts(&image(Trackpad::Left, 0)));
+        assert!(stream.accepts(&image(Trackpad::Right, 0)));
+
+        stream.advance(&image(Trackpad::Right, 0), now);
+        assert_eq!(stream.next_offset, 28);
+        assert!(!stream.accepts(&image(Trackpad::Right, 28)));
+        assert!(stream.due(now + RAW_DATA_INTERVAL));
+    }
+
+    #[test]
+    fn offsets_start_over_when_not_echoed() {
+        let now = Instant::now();
+        let mut stream = RawDataStream::new(Trackpad::Left);
+        stream.advance(&image(Trackpad::Left, 0), now);
+        stream.advance(&image(Trackpad::Left, 28), now);
+        assert_eq!(stream.next_offset, 56);
+        stream.advance(&image(Trackpad::Left, 0), now);
+        assert_eq!(stream.next_offset, 0);
+    }
+}
@@ -0,0 +1,140 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Trigger {
+    Left,
+    Right,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TriggerStage {
+    #[default]
+    Released,
+    SoftPull,
+    FullPull,
+}
+
+/// Thresholds are on the calibrated trigger travel, 0..1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TriggerThresholds {
+    pub soft_pull: f32,
+    pub full_pull: f32,
+    /// How far below a threshold the trigger has to return to leave that stage.
+    pub hysteresis: f32,
+    /// Treat the firmware's digital trigger bit as a full pull regardless of travel.
+    pub full_pull_on_digital: bool,
+}
+
+impl Default for TriggerThresholds {
+    fn default() -> Self {
+        TriggerThresholds {
+            soft_pull: 0.3,
+            full_pull: 0.9,
+            hysteresis: 0.05,
+            full_pull_on_digital: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TriggerStageTracker {
+    thresholds: TriggerThresholds,
+    stage: TriggerStage,
+}
+
+impl TriggerStageTracker {
+    pub fn new(thresholds: TriggerThresholds) -> TriggerStageTracker {
+        TriggerStageTracker {
+            thresholds,
+            stage: TriggerStage::Released,
+        }
+    }
+
+    pub fn thresholds(&self) -> TriggerThresholds {
+        self.thresholds
+    }
+
+    pub fn stage(&self) -> TriggerStage {
+        self.stage
+    }
+
+    /// Returns the previous stage if it changed.
+    pub fn update(&mut self, value: f32, digital: bool) -> Option<TriggerStage> {
+        let t = &self.thresholds;
+        let reached = |threshold: f32, stage: TriggerStage| {
+            if self.stage >= stage {
+                value >= threshold - t.hysteresis
+            } else {
+                value >= threshold
+            }
+        };
+
+        let stage = if (digital && t.full_pull_on_digital)
+            || reached(t.full_pull, TriggerStage::FullPull)
+        {
+            TriggerStage::FullPull
+        } else if reached(t.soft_pull, TriggerStage::SoftPull) {
+            TriggerStage::SoftPull
+        } else {
+            TriggerStage::Released
+        };
+
+        if stage != self.stage {
+            Some(std::mem::replace(&mut self.stage, stage))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_in_order() {
+        let mut tracker = TriggerStageTracker::new(TriggerThresholds::default());
+
+        assert_eq!(tracker.update(0.2, false), None);
+        assert_eq!(tracker.update(0.3, false), Some(TriggerStage::Released));
+        assert_eq!(tracker.stage(), TriggerStage::SoftPull);
+        assert_eq!(tracker.update(0.95, false), Some(TriggerStage::SoftPull));
+        assert_eq!(tracker.stage(), TriggerStage::FullPull);
+        assert_eq!(tracker.update(0.0, false), Some(TriggerStage::FullPull));
+        assert_eq!(tracker.stage(), TriggerStage::Released);
+    }
+
+    #[test]
+    fn hysteresis_keeps_stage() {
+        let mut tracker = TriggerStageTracker::new(TriggerThresholds::default());
+
+        tracker.update(0.92, false);
+        assert_eq!(tracker.update(0.86, false), None);
+        assert_eq!(tracker.stage(), TriggerStage::FullPull);
+        assert_eq!(tracker.update(0.84, false), Some(TriggerStage::FullPull));
+        assert_eq!(tracker.stage(), TriggerStage::SoftPull);
+        // Has to reach the threshold again, not just the hysteresis band
+        assert_eq!(tracker.update(0.88, false), None);
+
+        assert_eq!(tracker.update(0.26, false), None);
+        assert_eq!(tracker.update(0.24, false), Some(TriggerStage::SoftPull));
+        assert_eq!(tracker.update(0.28, false), None);
+        assert_eq!(tracker.stage(), TriggerStage::Released);
+    }
+
+    #[test]
+    fn digital_bit_is_full_pull() {
+        let mut tracker = TriggerStageTracker::new(TriggerThresholds::default());
+        assert_eq!(tracker.update(0.5, true), Some(TriggerStage::Released));
+        assert_eq!(tracker.stage(), TriggerStage::FullPull);
+
+        let mut tracker = TriggerStageTracker::new(TriggerThresholds {
+            full_pull_on_digital: false,
+            ..Default::default()
+        });
+        tracker.update(0.5, true);
+        assert_eq!(tracker.stage(), TriggerStage::SoftPull);
+    }
+}